@@ -1,88 +1,754 @@
 use serde::{Serialize, Deserialize};
-use gdnative::{ToVariant, FromVariant};
-use std::collections::HashMap;
-use itertools::{Itertools, Either};
+use rand::Rng;
+use std::{collections::HashMap, fs::File, path::{Path, PathBuf}};
 
-use crate::systems::items;
+use crate::{systems::items, util::{error, rng::{self, Stream}}};
 
-#[derive(ToVariant, FromVariant)]
-pub struct RecipeVariant {
-    input: Vec<(items::Item, u64)>,
-    output: Vec<items::Stack>,
+const FORGE: &'static str = "forge";
+const ALCHEMY_TABLE: &'static str = "alchemy_table";
+const WORKBENCH: &'static str = "workbench";
+
+/// Kind of crafting station a recipe is made at.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Station {
+    Forge,
+    AlchemyTable,
+    Workbench,
 }
 
-impl From<RecipeVariant> for Recipes {
-    fn from(r: RecipeVariant) -> Self {
-        Self {
-            input: r.input.into_iter().collect(),
-            output: r.output,
+impl Default for Station {
+    fn default() -> Self {
+        Self::Forge
+    }
+}
+
+impl Station {
+    pub const ALL_STATIONS: &'static [Self] = &[Self::Forge, Self::AlchemyTable, Self::Workbench];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Forge => FORGE,
+            Self::AlchemyTable => ALCHEMY_TABLE,
+            Self::Workbench => WORKBENCH,
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL_STATIONS.iter().find(|station| station.name() == name).copied()
+    }
 }
-impl From<Recipes> for RecipeVariant {
-    fn from(r: Recipes) -> Self {
+
+/// How a recipe becomes known. Meeting any one of them is enough.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// Known for the rest of the run once the item has been held.
+    Holding { item: items::ItemId },
+    /// Known for the rest of the run once the wave is reached.
+    Wave { wave: u64 },
+    /// Known in every later run once the item has been held.
+    Blueprint { item: items::ItemId },
+    /// Known in every later run once bought with banked currency.
+    Purchase { cost: u64 },
+}
+
+/// How much of each output a craft actually hands out.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Yield {
+    /// Share of every output count that is handed out.
+    #[serde(default = "Yield::full")]
+    pub rate: f64,
+    /// Every count is also scaled by a random factor within `1 ± spread`.
+    #[serde(default)]
+    pub spread: f64,
+}
+
+impl Default for Yield {
+    fn default() -> Self {
         Self {
-            input: r.input.into_iter().collect(),
-            output: r.output,
+            rate: Self::full(),
+            spread: 0.,
+        }
+    }
+}
+
+impl Yield {
+    fn full() -> f64 {
+        1.
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.rate == 1. && self.spread == 0.
+    }
+
+    fn is_valid(&self) -> bool {
+        self.rate >= 0. && (0. ..=1.).contains(&self.spread)
+    }
+
+    /// The largest share of an output a roll can hand out.
+    fn peak(&self) -> f64 {
+        self.rate * (1. + self.spread)
+    }
+
+    /// The most an output of `count` can come to.
+    fn most(&self, count: u64) -> u64 {
+        (count as f64 * self.peak()).ceil() as u64
+    }
+
+    /// What an output of `count` comes to on average.
+    pub fn expected(&self, count: u64) -> u64 {
+        (count as f64 * self.rate).round() as u64
+    }
+
+    /// Whole items are handed out as rolled, and a fraction of one only as often as that
+    /// fraction, so the yield never comes out above `rate` on average.
+    fn roll(&self, count: u64) -> u64 {
+        if self.is_exact() {
+            return count;
         }
+        rng::with(Stream::Crafting, |rng| {
+            let jitter = if self.spread > 0. {
+                rng.gen_range(-self.spread, self.spread)
+            } else {
+                0.
+            };
+            let share = (count as f64 * self.rate * (1. + jitter)).max(0.);
+            let whole = share.floor();
+            let extra = if rng.gen::<f64>() < share - whole { 1 } else { 0 };
+            whole as u64 + extra
+        })
     }
 }
 
+/// One of several sets of extra outputs, picked by weight.
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone)]
-pub struct Recipes {
-    pub input: HashMap<items::Item, u64>,
+pub struct Outcome {
     pub output: Vec<items::Stack>,
+    pub weight: f64,
 }
 
+/// Extra outputs handed out some of the time.
+#[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone)]
-pub enum Error {
-    MissingItems {
-        missing: Vec<(items::Item, u64)>,
-        overflow: Option<Vec<items::Stack>>,
+pub struct BonusOutput {
+    pub chance: f64,
+    pub output: Vec<items::Stack>,
+}
+
+/// Chances of outputs turning out better than normal.
+#[derive(Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct QualityOdds {
+    #[serde(default)]
+    pub fine: f64,
+    #[serde(default)]
+    pub masterwork: f64,
+}
+
+impl QualityOdds {
+    fn roll(&self, rng: &mut impl Rng) -> items::Quality {
+        let roll: f64 = rng.gen();
+        if roll < self.masterwork {
+            items::Quality::Masterwork
+        } else if roll < self.masterwork + self.fine {
+            items::Quality::Fine
+        } else {
+            items::Quality::Normal
+        }
+    }
+}
+
+/// An optional input. When the player opts in and holds it, it is used up and shifts the odds of the craft.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Catalyst {
+    pub item: items::ItemId,
+    pub count: u64,
+    #[serde(default)]
+    pub fine: f64,
+    #[serde(default)]
+    pub masterwork: f64,
+    /// Added to the chance of the bonus output.
+    #[serde(default)]
+    pub bonus: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Recipes {
+    pub id: String,
+    pub input: HashMap<items::ItemId, u64>,
+    pub output: Vec<items::Stack>,
+    #[serde(default)]
+    pub station: Station,
+    #[serde(default)]
+    pub yields: Yield,
+    /// One of these is handed out on top of `output`.
+    #[serde(default)]
+    pub outcomes: Vec<Outcome>,
+    #[serde(default)]
+    pub bonus: Option<BonusOutput>,
+    #[serde(default)]
+    pub quality: QualityOdds,
+    #[serde(default)]
+    pub catalysts: Vec<Catalyst>,
+    /// Recipes without any conditions are known from the start.
+    #[serde(default)]
+    pub discovery: Vec<Condition>,
+    /// Adds a recipe that breaks the outputs back down into the inputs at this yield.
+    #[serde(default)]
+    pub salvage: Option<Yield>,
+}
+
+/// Why a single recipe was rejected.
+#[derive(Debug)]
+pub enum Problem {
+    NoOutput,
+    UnknownItem(items::ItemId),
+    ZeroCount(items::ItemId),
+    /// Output larger than a single stack of the item can hold.
+    Unstackable(items::Stack),
+    DuplicateId,
+    /// Negative rate, a spread outside of `[0, 1]`, a rate above one or salvage that can
+    /// return more than went in.
+    BadYield(Yield),
+    /// A negative weight or chance, or quality chances adding up to more than one.
+    BadOdds,
+    /// Same inputs and outputs as the named recipe.
+    DuplicateOf(String),
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    File(PathBuf, error::JsonIOError),
+    Recipe {
+        file: PathBuf,
+        recipe: String,
+        problems: Vec<Problem>,
     },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    MissingItems(Vec<(items::ItemId, u64)>),
     OverflowItems(Vec<items::Stack>),
+    Inventory(items::Error),
+}
+
+/// What kept a batch from being any larger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+    /// As many as were asked for were made.
+    Requested,
+    /// Ran out of this input.
+    Input(items::ItemId),
+    /// Neither the inventory nor the stash has room for more outputs.
+    Space,
+    /// Hit `Recipes::MAX_BATCH`.
+    BatchSize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub crafted: u64,
+    /// Outputs that were handed out.
+    pub made: Vec<items::Stack>,
+    pub limit: Limit,
+}
+
+impl From<items::Error> for Error {
+    fn from(e: items::Error) -> Self {
+        match e {
+            items::Error::Rejected { missing, .. } if missing.len() != 0 => Self::MissingItems(missing),
+            items::Error::Rejected { overflow, .. } => Self::OverflowItems(overflow),
+            e => Self::Inventory(e),
+        }
+    }
 }
 
 impl Recipes {
-    pub fn attempt_craft(&self, inv: &mut items::Inventory) -> Result<
-        Option<Vec<items::Stack>>,
-        Error
-    > {
-        let inputs: Vec<_> = self.input
+    /// Most crafts a single batch may contain.
+    pub const MAX_BATCH: u64 = 999;
+
+    fn problems(&self) -> Vec<Problem> {
+        let registry = items::registry();
+        let mut problems = vec![];
+        if self.output.len() == 0 {
+            problems.push(Problem::NoOutput);
+        }
+        let extra_outputs = self.outcomes
             .iter()
-            .map(|(item, count)| inv.attempt_take(item.clone(), *count))
-            .collect();
-        if inputs.iter().any(|res| res.is_err()) {
-            let (add_back, errors): (Vec<_>, Vec<_>) = inputs
+            .flat_map(|outcome| outcome.output.iter())
+            .chain(self.bonus.iter().flat_map(|bonus| bonus.output.iter()));
+        let counts = self.input
+            .iter()
+            .map(|(item, count)| (item, *count))
+            .chain(self.output.iter().chain(extra_outputs).map(|stack| (&stack.item, stack.count)))
+            .chain(self.catalysts.iter().map(|catalyst| (&catalyst.item, catalyst.count)));
+        for (item, count) in counts {
+            if registry.get(item).is_none() {
+                problems.push(Problem::UnknownItem(item.clone()));
+            } else if count == 0 {
+                problems.push(Problem::ZeroCount(item.clone()));
+            }
+        }
+        for stack in self.output.iter() {
+            if registry.get(&stack.item).is_some() && stack.count > registry.max_in_stack(&stack.item) {
+                problems.push(Problem::Unstackable(stack.clone()));
+            }
+        }
+        if !self.yields.is_valid() || self.yields.rate > 1. {
+            problems.push(Problem::BadYield(self.yields));
+        }
+        let mut chances = self.outcomes
+            .iter()
+            .map(|outcome| outcome.weight)
+            .chain(self.bonus.iter().map(|bonus| bonus.chance))
+            .chain(vec![self.quality.fine, self.quality.masterwork])
+            .chain(self.catalysts.iter().flat_map(|c| vec![c.fine, c.masterwork, c.bonus]));
+        if chances.any(|chance| chance < 0.) || self.quality.fine + self.quality.masterwork > 1. {
+            problems.push(Problem::BadOdds);
+        }
+        if let Some(salvage) = self.salvage {
+            if !salvage.is_valid() || salvage.peak() > 1. {
+                problems.push(Problem::BadYield(salvage));
+            }
+        }
+        for condition in self.discovery.iter() {
+            match condition {
+                Condition::Holding { item } | Condition::Blueprint { item } if registry.get(item).is_none() => {
+                    problems.push(Problem::UnknownItem(item.clone()));
+                },
+                _ => (),
+            }
+        }
+        problems
+    }
+
+    /// The reverse of this recipe, if it can be salvaged. It shows up once
+    /// the outputs have been held.
+    fn salvage_recipe(&self) -> Option<Recipes> {
+        let salvage = self.salvage?;
+        Some(Recipes {
+            id: format!("salvage_{}", self.id),
+            input: self.output
+                .iter()
+                .map(|stack| (stack.item.clone(), stack.count))
+                .collect(),
+            output: self.input
+                .iter()
+                .map(|(item, count)| items::Stack { item: item.clone(), count: *count })
+                .collect(),
+            station: self.station,
+            yields: salvage,
+            outcomes: vec![],
+            bonus: None,
+            quality: Default::default(),
+            catalysts: vec![],
+            discovery: self.output
+                .iter()
+                .map(|stack| Condition::Holding { item: stack.item.clone() })
+                .collect(),
+            salvage: None,
+        })
+    }
+
+    fn is_same_as(&self, other: &Self) -> bool {
+        let mut own_output: Vec<_> = self.output.iter().map(|s| (&s.item, s.count)).collect();
+        let mut other_output: Vec<_> = other.output.iter().map(|s| (&s.item, s.count)).collect();
+        own_output.sort();
+        other_output.sort();
+        self.input == other.input && own_output == other_output
+    }
+
+    /// Loads every recipe file in `dir`, in file name order. Only recipes that
+    /// pass validation are returned; everything else is reported.
+    pub fn load_dir(dir: &Path) -> (Vec<Recipes>, Vec<LoadError>) {
+        let mut files = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+                .collect::<Vec<_>>(),
+            Err(e) => return (vec![], vec![LoadError::File(dir.to_owned(), e.into())]),
+        };
+        files.sort();
+
+        let mut accepted: Vec<Recipes> = vec![];
+        let mut errors = vec![];
+        for file in files {
+            log::info!("Loading recipes from {:?}.", file);
+            let loaded: Vec<Recipes> = match File::open(&file)
+                .map_err(error::JsonIOError::IO)
+                .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    errors.push(LoadError::File(file, e));
+                    continue;
+                },
+            };
+            let loaded = loaded
                 .into_iter()
-                .partition_map(|res| match res {
-                    Ok(add_back) => Either::Left(add_back),
-                    Err(e) => Either::Right(e),
+                .flat_map(|recipe| {
+                    let salvage = recipe.salvage_recipe();
+                    std::iter::once(recipe).chain(salvage)
                 });
-            let missing = errors
-                .into_iter()
-                .filter_map(|e| match e {
-                    items::Error::TooManyItems(item, count) => {
-                        log::error!("Crafting formula is incorrect! Request for {} of item {:?} exceeds maximum inventory load.", count, item);
-                        None
-                    },
-                    items::Error::NotEnoughItems(item, count) => {
-                        Some((item, count))
-                    },
-                    e => {
-                        log::warn!("Unexpected error {:?}!", e);
-                        None
+            for recipe in loaded {
+                let mut problems = recipe.problems();
+                for other in accepted.iter() {
+                    if other.id == recipe.id {
+                        problems.push(Problem::DuplicateId);
+                    } else if other.is_same_as(&recipe) {
+                        problems.push(Problem::DuplicateOf(other.id.clone()));
                     }
-                })
-                .collect();
-            let overflow = inv.attempt_add(add_back);
-            return Err(Error::MissingItems {
-                missing,
-                overflow,
-            });
+                }
+                if problems.len() == 0 {
+                    accepted.push(recipe);
+                } else {
+                    errors.push(LoadError::Recipe {
+                        file: file.clone(),
+                        recipe: recipe.id,
+                        problems,
+                    });
+                }
+            }
+        }
+        (accepted, errors)
+    }
+
+    /// Inputs come from the inventory first and the stash second, and outputs
+    /// that do not fit in the inventory go to the stash. Catalysts are only used
+    /// when asked for and held. Returns the outputs as rolled.
+    fn stage(
+        &self,
+        use_catalysts: bool,
+        from_inv: &mut items::Transaction,
+        from_stash: &mut items::Transaction,
+    ) -> Vec<items::Stack> {
+        for (item, count) in self.input.iter() {
+            take_from_either(item, *count, from_inv, from_stash);
+        }
+        let mut odds = self.quality;
+        let mut bonus = 0.;
+        for catalyst in self.catalysts.iter().filter(|_| use_catalysts) {
+            let held = from_inv.count_any_quality(&catalyst.item) + from_stash.count_any_quality(&catalyst.item);
+            if held >= catalyst.count {
+                take_from_either(&catalyst.item, catalyst.count, from_inv, from_stash);
+                odds.fine += catalyst.fine;
+                odds.masterwork += catalyst.masterwork;
+                bonus += catalyst.bonus;
+            }
+        }
+        let output = self.roll_output(odds, bonus);
+        from_inv.add(output.clone());
+        from_stash.add(from_inv.take_overflow());
+        output
+    }
+
+    /// Whether the outputs can vary from one craft to the next.
+    pub fn is_deterministic(&self) -> bool {
+        self.yields.is_exact()
+            && self.outcomes.len() == 0
+            && self.bonus.is_none()
+            && self.quality == QualityOdds::default()
+            && self.catalysts.len() == 0
+    }
+
+    fn roll_output(&self, odds: QualityOdds, bonus_chance: f64) -> Vec<items::Stack> {
+        let mut output: Vec<_> = self.output
+            .iter()
+            .map(|stack| items::Stack { item: stack.item.clone(), count: self.yields.roll(stack.count) })
+            .filter(|stack| stack.count != 0)
+            .collect();
+        rng::with(Stream::Crafting, |rng| {
+            let total: f64 = self.outcomes.iter().map(|outcome| outcome.weight).sum();
+            if total > 0. {
+                let mut bucket = rng.gen_range(0., total);
+                // Float error can leave the bucket just past the last weight.
+                let picked = self.outcomes
+                    .iter()
+                    .find(|outcome| {
+                        let hit = bucket < outcome.weight;
+                        bucket -= outcome.weight;
+                        hit
+                    })
+                    .or_else(|| self.outcomes.last());
+                if let Some(outcome) = picked {
+                    output.extend(outcome.output.iter().cloned());
+                }
+            }
+            if let Some(bonus) = self.bonus.as_ref() {
+                if rng.gen::<f64>() < bonus.chance + bonus_chance {
+                    output.extend(bonus.output.iter().cloned());
+                }
+            }
+            let registry = items::registry();
+            for stack in output.iter_mut() {
+                if let Some(item) = registry.qualified(&stack.item, odds.roll(rng)) {
+                    stack.item = item;
+                }
+            }
+        });
+        output
+    }
+
+    /// The largest outputs a single craft can hand out, at normal quality.
+    fn most_output(&self) -> Vec<items::Stack> {
+        let largest_outcome = self.outcomes
+            .iter()
+            .max_by_key(|outcome| outcome.output.iter().map(|stack| stack.count).sum::<u64>());
+        self.output
+            .iter()
+            .map(|stack| items::Stack { item: stack.item.clone(), count: self.yields.most(stack.count) })
+            .chain(largest_outcome.into_iter().flat_map(|outcome| outcome.output.iter().cloned()))
+            .chain(self.bonus.iter().flat_map(|bonus| bonus.output.iter().cloned()))
+            .collect()
+    }
+
+    /// Every id an output of `item` can come out as. Catalysts count as if used.
+    fn possible_ids(&self, item: &str) -> Vec<items::ItemId> {
+        let registry = items::registry();
+        let fine = self.quality.fine + self.catalysts.iter().map(|c| c.fine).sum::<f64>();
+        let masterwork = self.quality.masterwork + self.catalysts.iter().map(|c| c.masterwork).sum::<f64>();
+        let better = vec![(items::Quality::Fine, fine), (items::Quality::Masterwork, masterwork)]
+            .into_iter()
+            .filter(|(_, chance)| *chance > 0.)
+            .filter_map(|(quality, _)| registry.qualified(item, quality));
+        std::iter::once(item.to_owned()).chain(better).collect()
+    }
+
+    /// Whether crafting `times` at once would succeed even with the largest outputs.
+    /// Every quality an output can roll needs its own slots, so room is kept for all
+    /// of it turning out in each. Nothing is rolled or committed.
+    fn fits(&self, times: u64, inv: &mut items::Inventory, stash: &mut items::Inventory) -> bool {
+        let mut from_inv = inv.transaction();
+        let mut from_stash = stash.transaction();
+        for (item, count) in self.input.iter() {
+            take_from_either(item, count * times, &mut from_inv, &mut from_stash);
+        }
+        let output = self.most_output()
+            .into_iter()
+            .flat_map(|stack| self
+                .possible_ids(&stack.item)
+                .into_iter()
+                .map(move |item| items::Stack { item, count: stack.count * times }))
+            .collect();
+        from_inv.add(output);
+        from_stash.add(from_inv.take_overflow());
+        from_inv.is_valid() && from_stash.is_valid()
+    }
+
+    /// Crafts up to `count` times, or as many times as possible if `count` is `None`,
+    /// as a single transaction. Crafting nothing is not an error, check the limit.
+    pub fn craft_batch(
+        &self,
+        count: Option<u64>,
+        use_catalysts: bool,
+        inv: &mut items::Inventory,
+        stash: &mut items::Inventory,
+    ) -> Result<Batch, Error> {
+        let held = Plan::held(inv, stash);
+        let (by_input, short) = self.input
+            .iter()
+            .map(|(item, count)| (held.get(item).copied().unwrap_or(0) / count, Some(item)))
+            .min()
+            .unwrap_or((Self::MAX_BATCH, None));
+        let (mut target, mut limit) = match count {
+            Some(count) if count <= by_input => (count, Limit::Requested),
+            _ => (by_input, short.map_or(Limit::BatchSize, |item| Limit::Input(item.clone()))),
+        };
+        if target > Self::MAX_BATCH {
+            target = Self::MAX_BATCH;
+            limit = Limit::BatchSize;
+        }
+        // Room only ever runs out as the batch grows, so search for the largest one that fits.
+        if !self.fits(target, inv, stash) {
+            let (mut low, mut high) = (0, target);
+            while low + 1 < high {
+                let mid = low + (high - low) / 2;
+                if self.fits(mid, inv, stash) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            target = low;
+            limit = Limit::Space;
         }
+        let made = if target != 0 {
+            craft_all(std::iter::repeat(self).take(target as usize), use_catalysts, inv, stash)?
+        } else {
+            vec![]
+        };
+        Ok(Batch { crafted: target, made, limit })
+    }
+}
+
+/// Takes from the inventory first and the stash second.
+fn take_from_either(
+    item: &items::ItemId,
+    count: u64,
+    from_inv: &mut items::Transaction,
+    from_stash: &mut items::Transaction,
+) {
+    let in_inv = from_inv.count_any_quality(item).min(count);
+    from_inv.take_any_quality(item.clone(), in_inv);
+    from_stash.take_any_quality(item.clone(), count - in_inv);
+}
+
+/// Crafts every recipe in order as a single transaction, so later recipes can
+/// use the outputs of earlier ones. Returns every output handed out.
+fn craft_all<'a>(
+    recipes: impl Iterator<Item = &'a Recipes>,
+    use_catalysts: bool,
+    inv: &mut items::Inventory,
+    stash: &mut items::Inventory,
+) -> Result<Vec<items::Stack>, Error> {
+    let mut from_inv = inv.transaction();
+    let mut from_stash = stash.transaction();
+    let mut made = vec![];
+    for recipe in recipes {
+        made.extend(recipe.stage(use_catalysts, &mut from_inv, &mut from_stash));
+    }
+    // Only apply either half once both are known to succeed. Committing an
+    // invalid transaction applies nothing and reports why it was rejected.
+    if !from_inv.is_valid() {
+        from_inv.commit()?;
+    } else if !from_stash.is_valid() {
+        from_stash.commit()?;
+    } else {
+        from_inv.commit()?;
+        from_stash.commit()?;
+    }
+    Ok(made)
+}
+
+/// A chain of crafts that ends in a target recipe.
+#[derive(Default, Debug, Clone)]
+pub struct Plan {
+    /// Recipes in the order they have to be crafted, with how often.
+    pub steps: Vec<(Recipes, u64)>,
+    /// Held items the chain consumes.
+    pub materials: HashMap<items::ItemId, u64>,
+    /// Items that are neither held nor craftable from what is held.
+    pub shortfall: HashMap<items::ItemId, u64>,
+}
+
+impl Plan {
+    /// Works out the fewest crafts that make the inputs of `target` out of `held`,
+    /// preferring chains that come up short on as little as possible, and then `target` itself.
+    pub fn for_recipe(recipes: &[Recipes], target: &Recipes, held: HashMap<items::ItemId, u64>) -> Self {
+        let mut planning = Planning {
+            recipes,
+            plan: Default::default(),
+            held,
+        };
+        // Making an output of the target along the way would loop back on itself.
+        let mut visiting = target.output.iter().map(|stack| stack.item.clone()).collect();
+        planning.craft(target, 1, &mut visiting);
+        planning.plan
+    }
+
+    /// Counts of everything in the inventory and stash together, every quality
+    /// of an item counted under its plain id.
+    pub fn held(inv: &items::Inventory, stash: &items::Inventory) -> HashMap<items::ItemId, u64> {
+        let mut held = HashMap::new();
+        for stack in inv.stacks().chain(stash.stacks()) {
+            *held.entry(items::Quality::base_id(&stack.item).to_owned()).or_default() += stack.count;
+        }
+        held
+    }
+
+    pub fn craft_count(&self) -> u64 {
+        self.steps.iter().map(|(_, times)| times).sum()
+    }
+
+    fn shortfall_count(&self) -> u64 {
+        self.shortfall.values().sum()
+    }
 
-        Ok(inv.attempt_add(self.output.clone()))
+    pub fn is_complete(&self) -> bool {
+        self.steps.len() != 0 && self.shortfall.len() == 0
+    }
+
+    /// Runs every step as one transaction.
+    pub fn execute(&self, inv: &mut items::Inventory, stash: &mut items::Inventory) -> Result<(), Error> {
+        let recipes = self.steps
+            .iter()
+            .flat_map(|(recipe, times)| std::iter::repeat(recipe).take(*times as usize));
+        // Chains never spend catalysts, only crafts where the player asked for them do.
+        craft_all(recipes, false, inv, stash).map(|_| ())
+    }
+}
+
+#[derive(Clone)]
+struct Planning<'a> {
+    recipes: &'a [Recipes],
+    plan: Plan,
+    held: HashMap<items::ItemId, u64>,
+}
+
+impl<'a> Planning<'a> {
+    /// Uses held items first and crafts the rest.
+    fn require(&mut self, item: &items::ItemId, count: u64, visiting: &mut Vec<items::ItemId>) {
+        let held = self.held.entry(item.clone()).or_default();
+        let taken = (*held).min(count);
+        *held -= taken;
+        if taken != 0 {
+            *self.plan.materials.entry(item.clone()).or_default() += taken;
+        }
+        if count > taken {
+            self.craft_for(item, count - taken, visiting);
+        }
+    }
+
+    /// Tries every recipe that makes `item` and keeps the best outcome.
+    fn craft_for(&mut self, item: &items::ItemId, count: u64, visiting: &mut Vec<items::ItemId>) {
+        // Recipes that loop back to an item already being made cannot help.
+        if visiting.contains(item) {
+            *self.plan.shortfall.entry(item.clone()).or_default() += count;
+            return;
+        }
+        visiting.push(item.clone());
+        let recipes = self.recipes;
+        // Recipes with random or reduced outputs cannot be counted on.
+        let best = recipes
+            .iter()
+            .filter(|recipe| recipe.is_deterministic())
+            .filter_map(|recipe| {
+                let made: u64 = recipe.output
+                    .iter()
+                    .filter(|stack| &stack.item == item)
+                    .map(|stack| stack.count)
+                    .sum();
+                if made == 0 {
+                    return None;
+                }
+                let mut branch = self.clone();
+                branch.craft(recipe, (count + made - 1) / made, visiting);
+                *branch.held.entry(item.clone()).or_default() -= count;
+                Some(branch)
+            })
+            .min_by_key(|branch| (branch.plan.shortfall_count(), branch.plan.craft_count()));
+        visiting.pop();
+        match best {
+            Some(branch) => *self = branch,
+            None => *self.plan.shortfall.entry(item.clone()).or_default() += count,
+        }
+    }
+
+    fn craft(&mut self, recipe: &Recipes, times: u64, visiting: &mut Vec<items::ItemId>) {
+        for (input, count) in recipe.input.iter() {
+            self.require(input, count * times, visiting);
+        }
+        for output in recipe.output.iter() {
+            *self.held.entry(output.item.clone()).or_default() += output.count * times;
+        }
+        self.plan.steps.push((recipe.clone(), times));
     }
 }
@@ -9,6 +9,8 @@ mod forge;
 pub use forge::Forge;
 mod arena;
 pub use arena::Arena;
+mod pickup;
+pub use pickup::Pickup;
 
 mod projectile;
 pub use projectile::Normal as NormalProjectile;
@@ -3,28 +3,44 @@ use gdnative::{
     self as godot,
     GodotString,
     Instance,
+    KinematicBody2D,
     Node,
+    Node2D,
     NodePath,
     Object,
     PackedScene,
     init::{Property, PropertyHint, PropertyUsage, Signal, SignalArgument},
     ResourceLoader,
+    FromVariant,
     VariantArray,
     Variant,
 };
-use std::sync::{Arc, Mutex};
+use rand::Rng;
+use std::{collections::HashMap, sync::{Arc, Mutex}};
 use tap::TapOptionOps;
-use crate::{util::path_ops, records::{Record, Records}, entity::{Switch, Forge}};
+use crate::{
+    util::{conv, path_ops, rng::{self, Stream}, Group},
+    records::{Record, Records},
+    profile::Profile,
+    systems::{drops::PityCounters, health::{Faction, Source}, items::Stack},
+    entity::{Switch, Forge, Pickup, Player},
+};
 
 mod spawn;
 use spawn::{Cfg as SpawnCfg, System as SpawnSystem};
 mod wave;
 use wave::*;
+mod station;
+use station::Placement;
+mod recovery;
+use recovery::{Cfg as RecoveryCfg, Event as RecoveryEvent, System as RecoverySystem};
 
 #[derive(Debug)]
 struct Cfg {
     switch: GodotString,
     forge: GodotString,
+    stations: GodotString,
+    pickup: GodotString,
     ui: NodePath,
     world: NodePath,
     player: NodePath,
@@ -32,18 +48,27 @@ struct Cfg {
     arena_pos: na::Vector2<f64>,
 
     end_scene: GodotString,
+
+    /// Seed for the run. Zero picks a fresh one.
+    seed: u64,
 }
 
 impl Cfg {
     pub const WORLD: &'static str = "World";
     pub const UI: &'static str = "UI";
     pub const FORGE: &'static str = "res://forge/forge.tscn";
+    pub const STATIONS: &'static str = "res://forge/stations.json";
     pub const SWITCH: &'static str = "res://switch/switch.tscn";
+    pub const PICKUP: &'static str = "res://pickup/pickup.tscn";
+    /// How far from the fallen enemy drops may land.
+    pub const PICKUP_SCATTER: f64 = 12.;
     pub const PLAYER: &'static str = "Player";
     pub const ARENA_DIM: [f64; 2] = [944., 520.];
     pub const ARENA_POS: [f64; 2] = [40., 40.];
 
     pub const END_SCENE: &'static str = "res://roots/end_game.tscn";
+
+    pub const SEED: u64 = 0;
 }
 
 impl Default for Cfg {
@@ -51,13 +76,17 @@ impl Default for Cfg {
         Self {
             world: NodePath::from_str(Self::WORLD),
             forge: Self::FORGE.into(),
+            stations: Self::STATIONS.into(),
             ui: Self::UI.into(),
             switch: Self::SWITCH.into(),
+            pickup: Self::PICKUP.into(),
             player: Self::PLAYER.into(),
             arena_dim: na::Vector2::from_column_slice(&Self::ARENA_DIM),
             arena_pos: na::Vector2::from_column_slice(&Self::ARENA_POS),
 
             end_scene: Self::END_SCENE.into(),
+
+            seed: Self::SEED,
         }
     }
 }
@@ -66,6 +95,7 @@ impl Default for Cfg {
 struct Cache {
     switch_scene: Arc<Mutex<PackedScene>>,
     forge_scene: Arc<Mutex<PackedScene>>,
+    pickup_scene: Arc<Mutex<PackedScene>>,
 }
 
 impl Cache {
@@ -82,9 +112,14 @@ impl Cache {
                 .tap_none(|| log::info!("Failed to load scene from {:?}.", cfg.forge.to_string()))?;
             let loaded_forge = loaded_forge.cast()
                 .tap_none(|| log::info!("Failed to cast instanced scene {:?}.", cfg.forge.to_string()))?;
+            let loaded_pickup = loader.load(cfg.pickup.new_ref(), "PackedScene".into(), true)
+                .tap_none(|| log::info!("Failed to load scene from {:?}.", cfg.pickup.to_string()))?;
+            let loaded_pickup = loaded_pickup.cast()
+                .tap_none(|| log::info!("Failed to cast instanced scene {:?}.", cfg.pickup.to_string()))?;
             Some(Self {
                 switch_scene: Arc::new(Mutex::new(loaded_switch)),
                 forge_scene: Arc::new(Mutex::new(loaded_forge)),
+                pickup_scene: Arc::new(Mutex::new(loaded_pickup)),
             })
         })()
     }
@@ -98,15 +133,22 @@ pub struct Arena {
     cfg: Cfg,
     cache: Option<Cache>,
     spawned_switch_path: Option<NodePath>,
-    spawned_forge_path: Option<NodePath>,
+    spawned_station_paths: Vec<NodePath>,
+    stations: Vec<Placement>,
     spawn_sys: SpawnSystem,
-    spawn_count: u64,
+    recovery: RecoverySystem,
+    /// Template of every living spawn, keyed by instance id.
+    spawned: HashMap<i64, u64>,
+    pity: PityCounters,
     wave: Option<Wave>,
+    /// Enemies finished off by the player this run.
+    kills: u64,
 }
 
 impl Arena {
     const ARENA_READY: &'static str = "arena_ready";
     const WAVE_NUMBER_CHANGED: &'static str = "wave_num_changed";
+    const SEED_CHANGED: &'static str = "seed_changed";
 }
 
 impl godot::NativeClass for Arena {
@@ -139,6 +181,38 @@ impl godot::NativeClass for Arena {
             setter: |this: &mut Arena, dir: GodotString| this.spawn_sys.cfg.cfg_file = dir.to_string(),
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "recovery/wave_heal",
+            default: RecoveryCfg::WAVE_HEAL,
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.recovery.cfg.wave_heal,
+            setter: |this: &mut Arena, heal| this.recovery.cfg.wave_heal = heal,
+            usage: default_usage,
+        });
+        builder.add_property(Property {
+            name: "recovery/wave_heal_share",
+            default: RecoveryCfg::WAVE_HEAL_SHARE,
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.recovery.cfg.wave_heal_share,
+            setter: |this: &mut Arena, share| this.recovery.cfg.wave_heal_share = share,
+            usage: default_usage,
+        });
+        builder.add_property(Property {
+            name: "recovery/flawless_share",
+            default: RecoveryCfg::FLAWLESS_SHARE,
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.recovery.cfg.flawless_share,
+            setter: |this: &mut Arena, share| this.recovery.cfg.flawless_share = share,
+            usage: default_usage,
+        });
+        builder.add_property(Property {
+            name: "recovery/downtime_regen",
+            default: RecoveryCfg::DOWNTIME_REGEN,
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.recovery.cfg.downtime_regen,
+            setter: |this: &mut Arena, regen| this.recovery.cfg.downtime_regen = regen,
+            usage: default_usage,
+        });
         builder.add_property(Property {
             name: "world_root",
             default: NodePath::from_str(Cfg::WORLD),
@@ -163,6 +237,14 @@ impl godot::NativeClass for Arena {
             setter: |this: &mut Arena, forge| this.cfg.forge = forge,
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "station_file",
+            default: Cfg::STATIONS.into(),
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.cfg.stations.new_ref(),
+            setter: |this: &mut Arena, stations: GodotString| this.cfg.stations = stations,
+            usage: default_usage,
+        });
         builder.add_property(Property {
             name: "player",
             default: NodePath::from_str(Cfg::PLAYER),
@@ -179,6 +261,14 @@ impl godot::NativeClass for Arena {
             setter: |this: &mut Arena, switch: GodotString| this.cfg.switch = switch,
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "pickup_file",
+            default: Cfg::PICKUP.into(),
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.cfg.pickup.new_ref(),
+            setter: |this: &mut Arena, pickup: GodotString| this.cfg.pickup = pickup,
+            usage: default_usage,
+        });
         builder.add_property(Property {
             name: "end_scene",
             default: Cfg::END_SCENE.into(),
@@ -187,6 +277,14 @@ impl godot::NativeClass for Arena {
             setter: |this: &mut Arena, end| this.cfg.end_scene = end,
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "rng/seed",
+            default: Cfg::SEED,
+            hint: PropertyHint::None,
+            getter: |this: &Arena| this.cfg.seed,
+            setter: |this: &mut Arena, seed| this.cfg.seed = seed,
+            usage: default_usage,
+        });
         builder.add_signal(Signal {
             name: Self::ARENA_READY.into(),
             args: &[],
@@ -200,6 +298,15 @@ impl godot::NativeClass for Arena {
                 usage: PropertyUsage::DEFAULT,
             }],
         });
+        builder.add_signal(Signal {
+            name: Self::SEED_CHANGED.into(),
+            args: &[SignalArgument {
+                name: "seed",
+                default: Variant::from_u64(0),
+                hint: PropertyHint::None,
+                usage: PropertyUsage::DEFAULT,
+            }],
+        });
     }
 }
 
@@ -209,23 +316,28 @@ impl Arena {
             let mut world = unsafe { owner.get_node(self.cfg.world.new_ref())? };
             let cache = self.cache.as_ref()?;
 
-            let mut forge_instance = unsafe {
-                cache.forge_scene
-                    .lock().ok()
-                    .tap_none(|| log::warn!("Could not load forge scene."))?
-                    .instance(PackedScene::GEN_EDIT_STATE_INSTANCE)
-                    .tap_none(|| log::warn!("Could not instance forge scene."))?
-                    .cast()
-                    .tap_none(|| log::warn!("Could not cast instance forge to StaticBody2D."))?
-            };
-            unsafe { owner.get_node(self.cfg.ui.new_ref()) }
-                .tap_none(|| log::error!("Could not located UI node {}.", self.cfg.ui.to_string()))
-                .map(|n| Forge::call_instance_init(forge_instance, n));
-            unsafe {
-                world.add_child(Some(forge_instance.to_node()), false);
-                forge_instance.set_global_position(gdnative::Vector2::new(256., 300.));
-                let path = forge_instance.get_path();
-                self.spawned_forge_path = Some(path);
+            let wave_num = self.wave.as_ref().map_or(0, |wave| wave.num());
+            let ui = unsafe { owner.get_node(self.cfg.ui.new_ref()) }
+                .tap_none(|| log::error!("Could not located UI node {}.", self.cfg.ui.to_string()));
+            for placement in self.stations.iter().filter(|placement| placement.waves.contains(wave_num)) {
+                let mut forge_instance = unsafe {
+                    cache.forge_scene
+                        .lock().ok()
+                        .tap_none(|| log::warn!("Could not load forge scene."))?
+                        .instance(PackedScene::GEN_EDIT_STATE_INSTANCE)
+                        .tap_none(|| log::warn!("Could not instance forge scene."))?
+                        .cast()
+                        .tap_none(|| log::warn!("Could not cast instance forge to StaticBody2D."))?
+                };
+                if let Some(ui) = ui {
+                    Forge::call_instance_init(forge_instance, ui, placement.station);
+                }
+                unsafe {
+                    world.add_child(Some(forge_instance.to_node()), false);
+                    forge_instance.set_global_position(conv::na64_to_g(na::Vector2::from_column_slice(&placement.pos)));
+                    let path = forge_instance.get_path();
+                    self.spawned_station_paths.push(path);
+                }
             }
 
             let mut switch_instance = unsafe {
@@ -247,6 +359,43 @@ impl Arena {
             Some(())
         })();
     }
+
+    fn spawn_pickups(&self, owner: Node, pos: na::Vector2<f64>, drops: Vec<Stack>) {
+        (|| {
+            let mut world = unsafe { owner.get_node(self.cfg.world.new_ref())? };
+            let cache = self.cache.as_ref()?;
+            let player_path = path_ops::to_abs_if_exist(self.cfg.player.new_ref(), &owner)?;
+            let pickup_scene = cache.pickup_scene
+                .lock().ok()
+                .tap_none(|| log::warn!("Could not load pickup scene."))?;
+            for stack in drops {
+                let mut pickup_instance: Node2D = unsafe {
+                    pickup_scene
+                        .instance(PackedScene::GEN_EDIT_STATE_INSTANCE)
+                        .tap_none(|| log::warn!("Could not instance pickup scene."))?
+                        .cast()
+                        .tap_none(|| log::warn!("Could not cast instance pickup to Node2D."))?
+                };
+                let scatter = rng::with(Stream::Drops, |rng| na::Vector2::new(
+                    rng.gen_range(-Cfg::PICKUP_SCATTER, Cfg::PICKUP_SCATTER),
+                    rng.gen_range(-Cfg::PICKUP_SCATTER, Cfg::PICKUP_SCATTER),
+                ));
+                Pickup::call_instance_init(pickup_instance, player_path.new_ref(), &stack);
+                unsafe {
+                    world.add_child(Some(pickup_instance.to_node()), false);
+                    pickup_instance.set_global_position(conv::na64_to_g(pos + scatter));
+                }
+            }
+            Some(())
+        })();
+    }
+
+    /// Sweeps whatever loot is still on the floor into the player's inventory.
+    fn collect_pickups(&self, owner: Node) {
+        if let Some(mut tree) = unsafe { owner.get_tree() } {
+            unsafe { tree.call_group(Group::Pickup.into(), "collect".into(), &[]) };
+        }
+    }
 }
 
 #[methods]
@@ -257,9 +406,17 @@ impl Arena {
 
     #[export]
     fn _ready(&mut self, mut owner: Node) {
+        // Seed the run before anything rolls.
+        let seed = if self.cfg.seed == Cfg::SEED {
+            rand::random()
+        } else {
+            self.cfg.seed
+        };
+        rng::reseed(seed);
         // Load cache.
         self.spawn_sys.load_cache();
         self.cache = Cache::load_with(&self.cfg);
+        self.stations = Placement::load_all(self.cfg.stations.to_string());
         // Report on state.
         log::info!("Hello from arena! Loaded with cfg: {:?}. Firing ready event.", self.cfg);
         self.setup_next_wave(owner);
@@ -269,35 +426,75 @@ impl Arena {
                 Self::WAVE_NUMBER_CHANGED.into(),
                 &[Variant::from_u64(self.wave.as_ref().map_or(0, |wave| wave.num()))],
             );
+            owner.emit_signal(Self::SEED_CHANGED.into(), &[Variant::from_u64(seed)]);
         }
     }
 
     #[export]
-    fn _process(&mut self, _owner: Node, _delta: f64) {
+    fn _process(&mut self, owner: Node, delta: f64) {
+        let events = self.recovery.take_pending();
+        let regen = self.recovery.regen(delta);
+        if events.is_empty() && regen <= 0. {
+            return;
+        }
+        let recovery = &self.recovery;
+        self.with_player(owner, |player, base| {
+            for event in events {
+                match event {
+                    // Only damage taken during the wave counts against a flawless clear.
+                    RecoveryEvent::WaveStarted => {
+                        player.take_hurt();
+                    },
+                    RecoveryEvent::WaveCleared => {
+                        let flawless = !player.take_hurt();
+                        let heal = recovery.wave_heal(player.max_hp(), flawless);
+                        log::info!("Healing the player by {} for clearing the wave, flawless: {}.", heal, flawless);
+                        player.heal(base, heal);
+                    },
+                }
+            }
+            if regen > 0. {
+                player.heal(base, regen);
+            }
+        });
     }
 
     #[export]
-    fn remove_spawn(&mut self, owner: Node, removing: Object) {
-        self.spawn_count -= 1;
-        if let Some(enemy_obj) = unsafe { removing.cast() } {
-            let drops = Instance::<crate::entity::enemy::SimpleEnemy>::try_from_base(enemy_obj)
-                .map(|inst| inst.map_mut(|enemy, _| enemy.get_drops(self.wave.as_ref().map_or(1, |w| w.num()))));
-            if let Some(player) = unsafe { owner.get_node(self.cfg.player.new_ref()) }.and_then(|n| unsafe { n.cast() }) {
-                if let Some(Ok(drops)) = drops {
-                    log::info!("Handing out drops {:?} to player!", drops);
-                    Instance::<crate::entity::player::Player>::try_from_base(player)
-                        .map(|player| player.map_mut(|player, _| player.inventory.attempt_add(drops)));
-                }
-            }
+    fn remove_spawn(&mut self, owner: Node, removing: Object, killer: Variant) {
+        let template = match self.spawned.remove(&unsafe { removing.get_instance_id() }) {
+            Some(template) => template,
+            None => {
+                log::warn!("Ignoring a spawn that was already removed.");
+                return;
+            },
+        };
+        match Source::from_variant(&killer) {
+            Some(killer) if killer.faction == Faction::Player => {
+                self.kills += 1;
+                log::info!("Kill credited to {}.", killer.describe());
+            },
+            killer => log::info!("Spawn removed without kill credit, killed by {:?}.", killer),
         }
-        if self.spawn_count == 0 {
+        let table = self.spawn_sys.cache
+            .as_ref()
+            .and_then(|cache| cache.get_spawn(template))
+            .map(|data| &data.drops);
+        if let (Some(table), Some(enemy_obj)) = (table, unsafe { removing.cast::<Node2D>() }) {
+            let drops = table.generate_drops(self.wave.as_ref().map_or(1, |w| w.num()), &mut self.pity);
+            log::info!("Dropping {:?} as pickups!", drops);
+            let pos = conv::g_to_na64(unsafe { enemy_obj.get_global_position() });
+            self.spawn_pickups(owner, pos, drops);
+        }
+        if self.spawned.is_empty() {
+            self.recovery.wave_cleared();
+            self.collect_pickups(owner);
             self.setup_next_wave(owner);
         }
     }
 
     #[export]
     fn spawn_next_wave(&mut self, mut owner: Node) {
-        if let Some(path) = self.spawned_forge_path.take() {
+        for path in self.spawned_station_paths.drain(..) {
             unsafe {
                 owner
                     .get_node(path.new_ref())
@@ -316,6 +513,7 @@ impl Arena {
             None => Wave::initial(),
             Some(wave) => wave.successor(),
         };
+        self.recovery.wave_started();
         let world = unsafe { owner.get_node(self.cfg.world.new_ref()) };
         if let Some(player_path) = path_ops::to_abs_if_exist(self.cfg.player.new_ref(), &owner) {
             let spawns = self.spawn_sys.spawn_wave(
@@ -325,8 +523,8 @@ impl Arena {
                 self.cfg.arena_pos,
                 player_path,
             );
-            self.spawn_count = spawns.len() as u64;
-            for mut spawn in spawns {
+            for (template, mut spawn) in spawns {
+                self.spawned.insert(unsafe { spawn.get_instance_id() }, template);
                 unsafe {
                     spawn.connect(
                         "died".into(),
@@ -347,8 +545,29 @@ impl Arena {
         self.wave = Some(wave);
     }
 
+    /// Runs `f` on the player, if it is still around.
+    fn with_player<T>(&self, owner: Node, f: impl FnOnce(&mut Player, KinematicBody2D) -> T) -> Option<T> {
+        unsafe { owner.get_node(self.cfg.player.new_ref()) }
+            .and_then(|n| unsafe { n.cast::<KinematicBody2D>() })
+            .and_then(Instance::<Player>::try_from_base)
+            .and_then(|player| player.map_mut(f).ok())
+    }
+
+    /// Moves a share of what the player was holding into the persistent bank.
+    fn bank_loot(&self, owner: Node, loot: &[Stack]) -> u64 {
+        match Profile::get_autoload(owner).tap_none(|| log::warn!("Could not find the profile to bank into.")) {
+            Some(profile) => profile
+                .map_mut(|profile, _| profile.bank_loot(loot.iter()))
+                .unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// `loot` is what the player held when they died, sent along since the player
+    /// is still busy dying and cannot be asked.
     #[export]
-    fn end_game(&self, mut owner: Node) {
+    fn end_game(&self, mut owner: Node, cause: Variant, loot: Vec<Stack>) {
+        let banked = self.bank_loot(owner, &loot);
         unsafe {
             if let Some(tree) = owner.get_tree().as_mut() {
                 owner.queue_free();
@@ -360,7 +579,12 @@ impl Arena {
                 }
                 if let Some(records) = Records::get_autoload(owner) {
                     match records.map_mut(|record, _| record.add_record(Record {
-                        wave_num: self.wave.as_ref().map_or(0, |wave| wave.num())
+                        wave_num: self.wave.as_ref().map_or(0, |wave| wave.num()),
+                        seed: rng::seed(),
+                        banked,
+                        kills: self.kills,
+                        cause: Source::from_variant(&cause)
+                            .map_or_else(|| "unknown causes".to_owned(), |cause| cause.describe()),
                     })) {
                         Ok(_) => (),
                         Err(e) => log::info!("Failed to save records on game end! Encountered error: {:?}.", e),
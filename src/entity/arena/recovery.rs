@@ -0,0 +1,79 @@
+/// How the player recovers between waves.
+#[derive(Debug)]
+pub(super) struct Cfg {
+    /// Hp healed on clearing a wave.
+    pub wave_heal: f64,
+    /// Share of the maximum hp healed on clearing a wave, on top of `wave_heal`.
+    pub wave_heal_share: f64,
+    /// Extra share of the maximum hp healed when the wave was cleared without taking damage.
+    pub flawless_share: f64,
+    /// Hp regenerated per second while waiting for the next wave.
+    pub downtime_regen: f64,
+}
+
+impl Cfg {
+    pub const WAVE_HEAL: f64 = 0.;
+    pub const WAVE_HEAL_SHARE: f64 = 0.25;
+    pub const FLAWLESS_SHARE: f64 = 0.1;
+    pub const DOWNTIME_REGEN: f64 = 0.;
+}
+
+impl Default for Cfg {
+    fn default() -> Self {
+        Self {
+            wave_heal: Self::WAVE_HEAL,
+            wave_heal_share: Self::WAVE_HEAL_SHARE,
+            flawless_share: Self::FLAWLESS_SHARE,
+            downtime_regen: Self::DOWNTIME_REGEN,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Event {
+    WaveStarted,
+    WaveCleared,
+}
+
+/// Recovery owed to the player. Events are queued rather than settled on the spot,
+/// since they fire from signals that may arrive while the player is busy.
+#[derive(Default, Debug)]
+pub(super) struct System {
+    pub cfg: Cfg,
+    downtime: bool,
+    pending: Vec<Event>,
+}
+
+impl System {
+    pub fn wave_started(&mut self) {
+        self.downtime = false;
+        self.pending.push(Event::WaveStarted);
+    }
+
+    pub fn wave_cleared(&mut self) {
+        self.downtime = true;
+        self.pending.push(Event::WaveCleared);
+    }
+
+    pub fn take_pending(&mut self) -> Vec<Event> {
+        std::mem::replace(&mut self.pending, vec![])
+    }
+
+    pub fn wave_heal(&self, max_hp: f64, flawless: bool) -> f64 {
+        let share = if flawless {
+            self.cfg.wave_heal_share + self.cfg.flawless_share
+        } else {
+            self.cfg.wave_heal_share
+        };
+        (self.cfg.wave_heal + share * max_hp).max(0.)
+    }
+
+    /// Hp to regenerate over `delta` seconds, nothing while a wave is on.
+    pub fn regen(&self, delta: f64) -> f64 {
+        if self.downtime {
+            (self.cfg.downtime_regen * delta).max(0.)
+        } else {
+            0.
+        }
+    }
+}
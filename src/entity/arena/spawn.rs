@@ -14,14 +14,14 @@ use crate::{
         arena::wave::Wave,
         enemy::Cfg as EnemyCfg
     },
-    systems::items,
+    systems::drops::DropTable,
     util::{error, path_ops},
 };
 
 #[derive(Debug)]
 pub(super) struct Data {
     pub scene: Arc<Mutex<PackedScene>>,
-    pub drops: items::DropTable,
+    pub drops: DropTable,
     pub cfg: EnemyCfg,
 }
 
@@ -47,7 +47,7 @@ impl Data {
         EnemyCfg::call_get_cfg(sc.instance(PackedScene::GEN_EDIT_STATE_INSTANCE)?)
     }
 
-    fn read_drop_cfg(dir: &Path) -> Result<items::DropTable, error::JsonIOError> {
+    fn read_drop_cfg(dir: &Path) -> Result<DropTable, error::JsonIOError> {
         let mut enemy_name = if let Some(file_name) = dir.file_name() {
             file_name.to_os_string()
         } else {
@@ -162,13 +162,12 @@ impl System {
         arena_dim: na::Vector2<f64>,
         arena_pos: na::Vector2<f64>,
         target: NodePath,
-    ) -> Vec<Node> {
+    ) -> Vec<(u64, Node)> {
         if let (Some(cache), Some(world)) = (self.cache.as_ref(), world.as_mut()) {
             wave.generate_spawns(cache, arena_pos, arena_dim)
                 .into_iter()
-                .map(|s| s.spawn(world, cache, target.new_ref()))
-                .filter_map(Result::ok)
-                .filter_map(|x| x)
+                .map(|s| (s.template(), s.spawn(world, cache, target.new_ref())))
+                .filter_map(|(template, spawned)| Some((template, spawned.ok()??)))
                 .collect()
         } else {
             if self.cache.is_none() {
@@ -0,0 +1,43 @@
+use serde::Deserialize;
+use std::fs::File;
+use tap::TapResultOps;
+
+use crate::{
+    crafting::Station,
+    systems::drops::Waves,
+    util::{error, path_ops},
+};
+
+/// Where a crafting station stands between waves, and in which.
+/// Waves are counted as the number cleared so far.
+#[derive(Deserialize)]
+#[derive(Debug, Clone)]
+pub(super) struct Placement {
+    pub station: Station,
+    pub pos: [f64; 2],
+    #[serde(default)]
+    pub waves: Waves,
+}
+
+impl Placement {
+    const FALLBACK_POS: [f64; 2] = [256., 300.];
+
+    /// A forge in every wave, as before stations could be configured.
+    fn fallback() -> Vec<Self> {
+        vec![Self {
+            station: Station::Forge,
+            pos: Self::FALLBACK_POS,
+            waves: Default::default(),
+        }]
+    }
+
+    pub fn load_all(path: String) -> Vec<Self> {
+        let path = path_ops::abs_asset(path);
+        log::info!("Loading station placements from {:?}.", path);
+        File::open(&path)
+            .map_err(error::JsonIOError::IO)
+            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            .tap_err(|e| log::error!("Could not load station placements due to {:?}, placing a forge.", e))
+            .unwrap_or_else(|_| Self::fallback())
+    }
+}
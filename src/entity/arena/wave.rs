@@ -2,7 +2,7 @@ use nalgebra as na;
 use std::time::Instant;
 use gdnative::{Node, Node2D, NodePath, PackedScene};
 
-use crate::{util::conv, entity::{arena::spawn::Cache, enemy::Cfg as EnemyCfg}};
+use crate::{util::{conv, rng::{self, Stream}}, entity::{arena::spawn::Cache, enemy::Cfg as EnemyCfg}};
 
 pub(super) struct Spawn {
     pos: na::Vector2<f64>,
@@ -10,6 +10,10 @@ pub(super) struct Spawn {
 }
 
 impl Spawn {
+    pub fn template(&self) -> u64 {
+        self.spawn_id
+    }
+
     pub fn spawn(self, root: &mut Node, cache: &Cache, target: NodePath) -> Result<Option<Node>, ()> {
         log::info!("Spawning enemy {} at {:?}.", self.spawn_id, self.pos);
         let enemy = if let Some(enemy) = cache.get_spawn(self.spawn_id) {
@@ -27,7 +31,6 @@ impl Spawn {
             if let Some(mut instance) = instance.and_then(|instance| instance.cast::<Node2D>()) {
                 instance.set_global_position(conv::na64_to_g(self.pos));
                 EnemyCfg::call_set_target(instance.to_node(), target);
-                EnemyCfg::call_set_drop_table(instance.to_node(), enemy.drops.clone());
             }
         }
         Ok(instance)
@@ -60,23 +63,24 @@ impl Wave {
         let enemy_models = cache.available_units(self.wave_num);
         let mut spawns = vec![];
         let uniform = Uniform::new_inclusive(0., 1.);
-        let mut rng = rand::thread_rng();
-        while wave_value > 0. {
-            use rand::seq::SliceRandom;
-            if let Some(&(id, picked)) = enemy_models.choose(&mut rand::thread_rng()) {
-                wave_value -= picked.cfg.value;
-                // TODO sample
-                spawns.push(Spawn {
-                    pos: na::Vector2::new(
-                         rng.sample(uniform),
-                         rng.sample(uniform),
-                    ).component_mul(&dim) + pos,
-                    spawn_id: id,
-                });
-            } else {
-                break;
+        rng::with(Stream::Spawns, |rng| {
+            while wave_value > 0. {
+                use rand::seq::SliceRandom;
+                if let Some(&(id, picked)) = enemy_models.choose(rng) {
+                    wave_value -= picked.cfg.value;
+                    // TODO sample
+                    spawns.push(Spawn {
+                        pos: na::Vector2::new(
+                             rng.sample(uniform),
+                             rng.sample(uniform),
+                        ).component_mul(&dim) + pos,
+                        spawn_id: id,
+                    });
+                } else {
+                    break;
+                }
             }
-        }
+        });
         spawns
     }
 
@@ -18,7 +18,7 @@ use crate::{
     systems::{
         self,
         EditorCfg,
-        health::System as HealthSys,
+        health::{System as HealthSys, Damage, DamageType, Faction, Source},
     },
 };
 
@@ -31,6 +31,8 @@ struct Cfg {
     cooldown_duration: Duration,
     target: Vec<GodotString>,
     max_hits: u64,
+    /// Status effects applied to everything hit.
+    statuses: Vec<String>,
 }
 
 impl Cfg {
@@ -54,6 +56,7 @@ impl Default for Cfg {
             dmg: Self::DMG,
             target: Self::TARGET.iter().map(|s| s.into()).collect(),
             max_hits: Self::MAX_HITS,
+            statuses: vec![],
         }
     }
 }
@@ -165,6 +168,28 @@ impl EditorCfg for Cfg {
             setter: move |this: &mut T, max_hits| get_mut(this).max_hits = max_hits,
             usage: *systems::DEFAULT_USAGE,
         });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "statuses",
+            default: StringArray::new(),
+            hint: PropertyHint::None,
+            getter: move |this: &T| {
+                let mut buf = StringArray::new();
+                for status in get(this).statuses.iter() {
+                    buf.push(&status.as_str().into());
+                }
+                buf
+            },
+            setter: move |this: &mut T, statuses: StringArray| {
+                let mut buf = Vec::with_capacity(statuses.len() as usize);
+                for i in 0..statuses.len() {
+                    buf.push(statuses.get(i).to_string());
+                }
+                get_mut(this).statuses = buf;
+            },
+            usage: *systems::DEFAULT_USAGE,
+        });
     }
 }
 
@@ -173,6 +198,7 @@ struct Data {
     remaining_cooldown_duration: Duration,
     remaining_animating_duration: Duration,
     remaining_hit_duration: Duration,
+    dmg_scale: f64,
     // TODO make this a hash map
     hit_counts: Vec<(NodePath, u64)>,
 }
@@ -244,6 +270,21 @@ impl NativeClass for Attack {
 }
 
 impl Attack {
+    pub const ATTACK: &'static str = "melee";
+
+    /// Hits are credited to the closest ancestor that belongs to a faction, as attacks are
+    /// usually grouped under a node of their own.
+    fn attacker(owner: Area2D) -> Node {
+        let mut node = unsafe { owner.get_parent() };
+        while let Some(ancestor) = node {
+            if Faction::of(ancestor) != Faction::Neutral {
+                return ancestor;
+            }
+            node = unsafe { ancestor.get_parent() };
+        }
+        unsafe { owner.to_node() }
+    }
+
     fn get_hit_objects(&self, owner: Area2D) -> Vec<Node> {
         let bodies = unsafe { owner.get_overlapping_bodies() };
         bodies
@@ -321,9 +362,17 @@ impl Attack {
         };
 
         if let Some(data) = self.data.as_mut() {
+            let attacker = Self::attacker(owner);
             for obj in hit {
                 data.add_hit_count(&obj);
-                HealthSys::call_damage(unsafe { obj.to_object() }, self.cfg.dmg);
+                HealthSys::call_damage(
+                    unsafe { obj.to_object() },
+                    Damage::new(
+                        self.cfg.dmg * data.dmg_scale,
+                        DamageType::Melee,
+                        Source::of(attacker, Self::ATTACK),
+                    ).with_statuses(self.cfg.statuses.clone()),
+                );
             }
 
             data.step_time(delta);
@@ -335,7 +384,7 @@ impl Attack {
 }
 
 impl Attack {
-    pub fn execute(&mut self, mut owner: Area2D, dir: Direction) {
+    pub fn execute(&mut self, mut owner: Area2D, dir: Direction, dmg_scale: f64) {
         use std::f64::consts::PI;
         let dir = dir.to_radians();
         if let Some(dir) = dir {
@@ -343,6 +392,7 @@ impl Attack {
                 remaining_cooldown_duration: self.cfg.cooldown_duration,
                 remaining_animating_duration: self.cfg.animation_duration,
                 remaining_hit_duration: self.cfg.hit_duration,
+                dmg_scale,
                 hit_counts: vec![],
             });
             // Orientation is upside down in screen space.
@@ -358,6 +408,7 @@ impl Attack {
                 remaining_cooldown_duration: self.cfg.cooldown_duration,
                 remaining_animating_duration: Duration::from_millis(0),
                 remaining_hit_duration: Duration::from_millis(0),
+                dmg_scale,
                 hit_counts: vec![],
             });
         }
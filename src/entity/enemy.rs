@@ -17,13 +17,23 @@ use crate::{
     util::{conv, Group, Direction},
     systems::{
         EditorCfg,
-        health::{System as HealthSys, Cfg as HealthCfg},
+        health::{System as HealthSys, Cfg as HealthCfg, Damage, DamageType, Source},
         aim::{System as AimSys},
-        items,
         DEFAULT_USAGE,
     },
 };
 
+/// What bumping into the player counts as.
+const CONTACT_ATTACK: &'static str = "contact";
+
+fn die(mut owner: KinematicBody2D, health: &HealthSys) {
+    // TODO Any other cleanup.
+    let var = owner.to_variant();
+    let killer = health.killer().map_or_else(Variant::new, Source::to_variant);
+    unsafe { owner.emit_signal("died".into(), &[var, killer]) };
+    unsafe { owner.queue_free() };
+}
+
 #[derive(Debug, Clone)]
 #[derive(ToVariant, FromVariant)]
 pub struct Cfg {
@@ -55,11 +65,6 @@ impl Cfg {
             owner.call("set_target".into(), &[target.to_variant()]);
         }
     }
-    pub fn call_set_drop_table(mut owner: Node, drop_table: items::DropTable) {
-        unsafe {
-            owner.call("set_drops".into(), &[drop_table.to_variant()]);
-        }
-    }
 }
 
 #[derive(Default, Debug)]
@@ -69,7 +74,6 @@ pub struct Data {
 #[derive(Default, Debug)]
 pub struct State {
     target: Option<NodePath>,
-    drop_table: Option<items::DropTable>,
 }
 
 #[derive(Default, Debug)]
@@ -98,12 +102,20 @@ impl NativeClass for SimpleEnemy {
 
         builder.add_signal(Signal {
             name: "died".into(),
-            args: &[SignalArgument {
-                name: "owner".into(),
-                default: Variant::new(),
-                hint: PropertyHint::None,
-                usage: PropertyUsage::DEFAULT,
-            }],
+            args: &[
+                SignalArgument {
+                    name: "owner".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+                SignalArgument {
+                    name: "killer".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+            ],
         });
     }
 }
@@ -119,7 +131,11 @@ impl SimpleEnemy {
     }
 
     fn calc_vel(&self) -> f64 {
-        Self::BASE_VELOCITY
+        if self.health.statuses.is_stunned() {
+            0.
+        } else {
+            Self::BASE_VELOCITY * self.health.statuses.speed_factor()
+        }
     }
 
     fn calc_dmg(&self) -> f64 {
@@ -146,8 +162,13 @@ impl SimpleEnemy {
     }
 
     #[export]
-    fn _process(&mut self, _owner: KinematicBody2D, delta: f64) {
-        self.health.process(Duration::from_secs_f64(delta));
+    fn _process(&mut self, owner: KinematicBody2D, delta: f64) {
+        if !self.health.is_dead() {
+            self.health.process(Duration::from_secs_f64(delta), None);
+            if self.health.is_dead() {
+                die(owner, &self.health);
+            }
+        }
     }
 
     #[export]
@@ -173,20 +194,28 @@ impl SimpleEnemy {
         let col = col.and_then(|col| unsafe { col.get_collider()?.cast::<Node>() });
         if let Some(col) = col {
             if Group::Player.has_node(col) {
-                HealthSys::call_damage(unsafe { col.to_object() }, self.calc_dmg());
+                HealthSys::call_damage(
+                    unsafe { col.to_object() },
+                    Damage::new(
+                        self.calc_dmg(),
+                        DamageType::Contact,
+                        Source::of(unsafe { owner.to_node() }, CONTACT_ATTACK),
+                    ),
+                );
             }
         }
     }
 
     #[export]
-    fn damage(&mut self, mut owner: KinematicBody2D, dmg: f64) {
+    fn damage(&mut self, owner: KinematicBody2D, dmg: Damage) {
+        // Already dying, waiting on the queued free.
+        if self.health.is_dead() {
+            return;
+        }
         log::info!("Damage applied!");
         self.health.damage(dmg, None);
         if self.health.is_dead() {
-            // TODO Any other cleanup.
-            let var = owner.to_variant();
-            unsafe { owner.emit_signal("died".into(), &[var]) };
-            unsafe { owner.queue_free() };
+            die(owner, &self.health);
         }
     }
 
@@ -199,18 +228,6 @@ impl SimpleEnemy {
     fn get_cfg(&mut self, _: KinematicBody2D) -> Cfg {
         self.cfg.clone()
     }
-
-    #[export]
-    fn set_drops(&mut self, _: KinematicBody2D, table: items::DropTable) {
-        self.state.drop_table = Some(table)
-    }
-
-    pub fn get_drops(&self, wave: u64) -> Vec<items::Stack> {
-        self.state.drop_table
-            .as_ref()
-            .map(|table| table.generate_drops(wave))
-            .unwrap_or(vec![])
-    }
 }
 
 #[derive(Default, Debug)]
@@ -243,12 +260,20 @@ impl NativeClass for RangedEnemy {
 
         builder.add_signal(Signal {
             name: "died".into(),
-            args: &[SignalArgument {
-                name: "owner".into(),
-                default: Variant::new(),
-                hint: PropertyHint::None,
-                usage: PropertyUsage::DEFAULT,
-            }],
+            args: &[
+                SignalArgument {
+                    name: "owner".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+                SignalArgument {
+                    name: "killer".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+            ],
         });
     }
 }
@@ -264,9 +289,13 @@ impl RangedEnemy {
     }
 
     fn calc_vel(&self) -> f64 {
+        if self.health.statuses.is_stunned() {
+            return 0.;
+        }
         self.aim.calc_vel(Direction::Up)
             .map(|v| v.norm())
             .unwrap_or(Self::BASE_VELOCITY)
+            * self.health.statuses.speed_factor()
     }
 
     fn calc_dmg(&self) -> f64 {
@@ -299,7 +328,18 @@ impl RangedEnemy {
     #[export]
     fn _process(&mut self, owner: KinematicBody2D, delta: f64) {
         let delta = Duration::from_secs_f64(delta);
-        self.health.process(delta);
+        if !self.health.is_dead() {
+            self.health.process(delta, None);
+            if self.health.is_dead() {
+                die(owner, &self.health);
+                return;
+            }
+        }
+        if self.health.statuses.is_stunned() {
+            self.aim.reset(unsafe { owner.to_node() });
+            self.shooting = Duration::from_millis(0);
+            return;
+        }
         self.aim.narrow_aim(unsafe { owner.to_node() }, delta);
         if self.aim.is_aiming() {
             self.shooting += delta;
@@ -308,6 +348,7 @@ impl RangedEnemy {
                     unsafe { conv::g_to_na64(owner.get_global_position()) },
                     unsafe { owner.to_node() },
                     self.aim.calc_dmg(),
+                    None,
                 );
                 self.shooting = Duration::from_millis(0);
             }
@@ -318,7 +359,13 @@ impl RangedEnemy {
 
     #[export]
     fn _physics_process(&mut self, mut owner: KinematicBody2D, delta: f64) {
-        let move_dir = if let Some(target) = self.get_target(&owner) {
+        // Stunned enemies neither move nor take aim.
+        let target = if self.health.statuses.is_stunned() {
+            None
+        } else {
+            self.get_target(&owner)
+        };
+        let move_dir = if let Some(target) = target {
             let targ_pos = conv::g_to_na64(unsafe { target.get_global_position() });
 
             let own_pos = conv::g_to_na64(unsafe { owner.get_global_position() });
@@ -352,20 +399,28 @@ impl RangedEnemy {
         let col = col.and_then(|col| unsafe { col.get_collider()?.cast::<Node>() });
         if let Some(col) = col {
             if Group::Player.has_node(col) {
-                HealthSys::call_damage(unsafe { col.to_object() }, self.calc_dmg());
+                HealthSys::call_damage(
+                    unsafe { col.to_object() },
+                    Damage::new(
+                        self.calc_dmg(),
+                        DamageType::Contact,
+                        Source::of(unsafe { owner.to_node() }, CONTACT_ATTACK),
+                    ),
+                );
             }
         }
     }
 
     #[export]
-    fn damage(&mut self, mut owner: KinematicBody2D, dmg: f64) {
+    fn damage(&mut self, owner: KinematicBody2D, dmg: Damage) {
+        // Already dying, waiting on the queued free.
+        if self.health.is_dead() {
+            return;
+        }
         log::info!("Damage applied!");
         self.health.damage(dmg, None);
         if self.health.is_dead() {
-            // TODO Any other cleanup.
-            let var = owner.to_variant();
-            unsafe { owner.emit_signal("died".into(), &[var]) };
-            unsafe { owner.queue_free() };
+            die(owner, &self.health);
         }
     }
 
@@ -378,16 +433,4 @@ impl RangedEnemy {
     fn get_cfg(&mut self, _: KinematicBody2D) -> Cfg {
         self.cfg.clone()
     }
-
-    #[export]
-    fn set_drops(&mut self, _: KinematicBody2D, table: items::DropTable) {
-        self.state.drop_table = Some(table)
-    }
-
-    pub fn get_drops(&self, wave: u64) -> Vec<items::Stack> {
-        self.state.drop_table
-            .as_ref()
-            .map(|table| table.generate_drops(wave))
-            .unwrap_or(vec![])
-    }
 }
@@ -12,20 +12,23 @@ use gdnative::{
     ToVariant,
 };
 use tap::TapResultOps;
-use crate::{util::Group, systems::{self, EditorCfg,}};
+use crate::{util::Group, systems::{self, EditorCfg, health::Damage}, crafting::Station};
 
 pub struct Cfg {
     crafting_ui: NodePath,
+    station: Station,
 }
 
 impl Cfg {
     const CRAFTING_UI: &'static str = "unknown";
+    const STATION: &'static str = "forge";
 }
 
 impl Default for Cfg {
     fn default() -> Self {
         Self {
             crafting_ui: NodePath::from_str(Self::CRAFTING_UI),
+            station: Default::default(),
         }
     }
 }
@@ -51,6 +54,21 @@ impl EditorCfg for Cfg {
             setter: move |this: &mut T, targ| get_mut(this).crafting_ui = targ,
             usage: *systems::DEFAULT_USAGE,
         });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "station",
+            default: GodotString::from_str(Self::STATION),
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).station.name().into(),
+            setter: move |this: &mut T, name: GodotString| {
+                match Station::from_name(&name.to_string()) {
+                    Some(station) => get_mut(this).station = station,
+                    None => log::warn!("Unknown station {:?}.", name.to_string()),
+                }
+            },
+            usage: *systems::DEFAULT_USAGE,
+        });
     }
 }
 
@@ -83,8 +101,12 @@ impl godot::NativeClass for Forge {
 #[methods]
 impl Forge {
     #[export]
-    fn instance_init(&mut self, owner: StaticBody2D, ui_node: Node) {
+    fn instance_init(&mut self, owner: StaticBody2D, ui_node: Node, station: GodotString) {
         self.cfg.crafting_ui = unsafe { ui_node.get_path() };
+        match Station::from_name(&station.to_string()) {
+            Some(station) => self.cfg.station = station,
+            None => log::warn!("Unknown station {:?}, keeping {:?}.", station.to_string(), self.cfg.station),
+        }
     }
 
     #[export]
@@ -95,13 +117,13 @@ impl Forge {
 
     #[export]
     fn switch(&self, owner: StaticBody2D) {
-        log::info!("Forge was hit!");
+        log::info!("{} was hit!", self.cfg.station.name());
         // TODO pause game and open crafting menu.
         unsafe {
             owner.to_node().emit_signal("to_forge".into(), &[]);
             if let Some(ui) = owner.get_node(self.cfg.crafting_ui.new_ref()).and_then(|n| n.cast()) {
                 if let Some(instance) = Instance::<crate::ui::UI>::try_from_base(ui) {
-                    instance.map_mut(|ui, base| ui.to_forge(base))
+                    instance.map_mut(|ui, base| ui.to_forge(base, self.cfg.station))
                         .tap_err(|e| log::error!("Could not transition to forge due to {:?}.", e));
                 }
             }
@@ -109,19 +131,20 @@ impl Forge {
     }
 
     #[export]
-    fn damage(&self, owner: StaticBody2D, _dmg: f64) {
+    fn damage(&self, owner: StaticBody2D, _dmg: Damage) {
         self.switch(owner);
     }
 }
 
 impl Forge {
-    pub fn call_instance_init(mut switch: StaticBody2D, ui: Node) {
+    pub fn call_instance_init(mut switch: StaticBody2D, ui: Node, station: Station) {
         let instance_init_method: GodotString = "instance_init".into();
         if unsafe { switch.has_method(instance_init_method.new_ref()) } {
             // TODO random aim based on how long was aimed for.
             unsafe {
                 switch.call(instance_init_method, &[
                     ui.to_variant(),
+                    Variant::from_str(station.name()),
                 ])
             };
         }
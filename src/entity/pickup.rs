@@ -0,0 +1,235 @@
+use gdnative::{
+    self as godot,
+    GodotString,
+    init::{Property, PropertyHint, ClassBuilder,},
+    Instance,
+    KinematicBody2D,
+    NativeClass,
+    Node2D,
+    NodePath,
+    user_data::MutexData,
+    Variant,
+};
+use std::time::Duration;
+use crate::{
+    util::{conv, Group},
+    systems::{self, EditorCfg, items::Stack},
+    entity::Player,
+};
+
+pub struct Cfg {
+    target: NodePath,
+    magnet_radius: f64,
+    pickup_radius: f64,
+    magnet_speed: f64,
+    lifetime: Duration,
+}
+
+impl Cfg {
+    const TARGET: &'static str = "unknown";
+    const MAGNET_RADIUS: f64 = 100.;
+    const PICKUP_RADIUS: f64 = 16.;
+    const MAGNET_SPEED: f64 = 300.;
+    const LIFETIME: u64 = 20000;
+}
+
+impl Default for Cfg {
+    fn default() -> Self {
+        Self {
+            target: NodePath::from_str(Self::TARGET),
+            magnet_radius: Self::MAGNET_RADIUS,
+            pickup_radius: Self::PICKUP_RADIUS,
+            magnet_speed: Self::MAGNET_SPEED,
+            lifetime: Duration::from_millis(Self::LIFETIME),
+        }
+    }
+}
+
+impl EditorCfg for Cfg {
+    fn register_properties<T, G, GM>(
+        builder: &ClassBuilder<T>,
+        get_proto: G,
+        get_mut_proto: GM,
+    )
+        where
+            T: Send + NativeClass<UserData = MutexData<T>>,
+            G: Clone + Fn(&T) -> &Self,
+            GM: Clone + Fn(&mut T) -> &mut Self,
+    {
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "target",
+            default: NodePath::from_str(Self::TARGET),
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).target.new_ref(),
+            setter: move |this: &mut T, targ| get_mut(this).target = targ,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "magnet_radius",
+            default: Self::MAGNET_RADIUS,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).magnet_radius,
+            setter: move |this: &mut T, radius| get_mut(this).magnet_radius = radius,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "pickup_radius",
+            default: Self::PICKUP_RADIUS,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).pickup_radius,
+            setter: move |this: &mut T, radius| get_mut(this).pickup_radius = radius,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "magnet_speed",
+            default: Self::MAGNET_SPEED,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).magnet_speed,
+            setter: move |this: &mut T, speed| get_mut(this).magnet_speed = speed,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "lifetime",
+            default: Self::LIFETIME,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).lifetime.as_millis() as u64,
+            setter: move |this: &mut T, millis| get_mut(this).lifetime = Duration::from_millis(millis),
+            usage: *systems::DEFAULT_USAGE,
+        });
+    }
+}
+
+/// Loot lying in the arena. Drifts towards the target once it is close enough and lands in its
+/// inventory on contact, unless it expires first.
+#[derive(Default)]
+pub struct Pickup {
+    cfg: Cfg,
+    stack: Option<Stack>,
+    alive_for: Duration,
+}
+
+impl godot::NativeClass for Pickup {
+    type Base = Node2D;
+    type UserData = MutexData<Pickup>;
+
+    fn class_name() -> &'static str {
+        "Pickup"
+    }
+
+    fn init(owner: Self::Base) -> Self {
+        Self::_init(owner)
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        Cfg::register_properties(builder, |this| &this.cfg, |this| &mut this.cfg);
+    }
+}
+
+impl Pickup {
+    fn target(&self, owner: Node2D) -> Option<KinematicBody2D> {
+        unsafe { owner.get_node(self.cfg.target.new_ref()).and_then(|n| n.cast()) }
+    }
+
+    /// Moves the stack into the target's inventory or stash. Whatever does not fit stays on the ground.
+    fn hand_over(&mut self, target: KinematicBody2D) -> bool {
+        let stack = if let Some(stack) = self.stack.take() {
+            stack
+        } else {
+            return true;
+        };
+        let added = Instance::<Player>::try_from_base(target)
+            .map(|player| player.map_mut(|player, _| player.receive(vec![stack.clone()])));
+        match added {
+            Some(Ok(Ok(()))) => true,
+            Some(Ok(Err(mut overflow))) if overflow.len() == 1 => {
+                log::info!("Could not pick up all of {:?}, leaving {:?}.", stack, overflow);
+                self.stack = overflow.pop();
+                false
+            },
+            e => {
+                log::warn!("Failed to hand over {:?} due to {:?}.", stack, e);
+                self.stack = Some(stack);
+                false
+            },
+        }
+    }
+}
+
+#[methods]
+impl Pickup {
+    fn _init(_owner: Node2D) -> Self {
+        Default::default()
+    }
+
+    #[export]
+    fn instance_init(&mut self, _owner: Node2D, target: NodePath, item: GodotString, count: u64) {
+        self.cfg.target = target;
+        self.stack = Some(Stack { item: item.to_string(), count });
+    }
+
+    #[export]
+    fn _ready(&self, owner: Node2D) {
+        Group::Pickup.add_node(unsafe { owner.to_node() });
+    }
+
+    #[export]
+    fn _process(&mut self, mut owner: Node2D, delta: f64) {
+        let delta = Duration::from_secs_f64(delta);
+        self.alive_for += delta;
+        if self.alive_for >= self.cfg.lifetime {
+            log::info!("Pickup {:?} despawned.", self.stack);
+            unsafe { owner.queue_free() };
+            return;
+        }
+        let target = if let Some(target) = self.target(owner) {
+            target
+        } else {
+            return;
+        };
+        let own_pos = conv::g_to_na64(unsafe { owner.get_global_position() });
+        let offset = conv::g_to_na64(unsafe { target.get_global_position() }) - own_pos;
+        let dist = offset.norm();
+        if dist <= self.cfg.pickup_radius {
+            if self.hand_over(target) {
+                unsafe { owner.queue_free() };
+            }
+        } else if dist <= self.cfg.magnet_radius {
+            let step = (self.cfg.magnet_speed * delta.as_secs_f64()).min(dist);
+            let pos = own_pos + offset / dist * step;
+            unsafe { owner.set_global_position(conv::na64_to_g(pos)) };
+        }
+    }
+
+    /// Hands the stack to the target regardless of distance. Whatever does not fit stays on the ground.
+    #[export]
+    fn collect(&mut self, mut owner: Node2D) {
+        if self.target(owner).map_or(false, |target| self.hand_over(target)) {
+            unsafe { owner.queue_free() };
+        }
+    }
+}
+
+impl Pickup {
+    pub fn call_instance_init(mut pickup: Node2D, target: NodePath, stack: &Stack) {
+        let instance_init_method: GodotString = "instance_init".into();
+        if unsafe { pickup.has_method(instance_init_method.new_ref()) } {
+            unsafe {
+                pickup.call(instance_init_method, &[
+                    Variant::from_node_path(&target),
+                    Variant::from_str(&stack.item),
+                    Variant::from_u64(stack.count),
+                ])
+            };
+        }
+    }
+}
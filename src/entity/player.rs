@@ -1,15 +1,16 @@
 use nalgebra as na;
 use gdnative::{
     self as godot,
-    init::Signal,
+    init::{PropertyHint, PropertyUsage, Signal, SignalArgument},
     InputEvent,
     InputEventMouseButton,
     InputEventMouseMotion,
     InputEventKey,
     KinematicBody2D,
+    ToVariant,
+    Variant,
 };
-use std::time::Duration;
-use tap::TapResultOps;
+use std::{collections::HashSet, time::Duration};
 use crate::{
     util::{
         Direction,
@@ -18,14 +19,17 @@ use crate::{
     },
     systems::{
         EditorCfg,
-        health::{System as HealthSys},
+        health::{System as HealthSys, Damage, DamageType, Source as DamageSource},
         items::Inventory,
         aim::{System as AimSys},
         dash::{System as DashSys},
         melee::{System as MeleeSys},
+        effects::Effect,
+        equipment::{Loadout, Slot},
+        stats::{Modifier, Source, Stat, Stats},
         items,
     },
-    crafting::{Recipes, RecipeVariant},
+    profile::{Bonus, Profile},
 };
 
 pub struct Player {
@@ -36,6 +40,9 @@ pub struct Player {
     remaining_accel: f64,
     melee_radius: f64,
 
+    stats: Stats,
+    buffs: Vec<(Source, Duration)>,
+
     dash: DashSys,
     aim: AimSys,
     melee: MeleeSys,
@@ -43,12 +50,22 @@ pub struct Player {
 
     // inventory
     pub inventory: Inventory,
+    /// Holds whatever does not fit in the inventory.
+    pub stash: Inventory,
+    pub equipment: Loadout,
+    /// Every item held at some point during the run.
+    held_ever: HashSet<items::ItemId>,
 }
 
 impl Player {
     const DEFAULT_BASE_SPEED: f64 = 140.;
     const DEFAULT_ACCEL_TIME: f64 = 1.;
     const MELEE_RADIUS: f64 = 30.;
+    const STASH_SLOTS: usize = 96;
+
+    const CONSUMABLE_PERK: &'static str = "consumables";
+
+    const STATUSES_CHANGED: &'static str = "statuses_changed";
 }
 
 impl Default for Player {
@@ -61,6 +78,9 @@ impl Default for Player {
             remaining_accel: Self::DEFAULT_ACCEL_TIME,
             melee_radius: Self::MELEE_RADIUS,
 
+            stats: Default::default(),
+            buffs: vec![],
+
             dash: Default::default(),
             aim: Default::default(),
             melee: Default::default(),
@@ -68,6 +88,9 @@ impl Default for Player {
 
             // Inventory
             inventory: Default::default(),
+            stash: Inventory::with_slots(Self::STASH_SLOTS),
+            equipment: Default::default(),
+            held_ever: Default::default(),
         }
     }
 }
@@ -92,16 +115,144 @@ impl godot::NativeClass for Player {
 
         builder.add_signal(Signal {
             name: "died".into(),
-            args: &[],
+            args: &[
+                SignalArgument {
+                    name: "cause".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+                SignalArgument {
+                    name: "loot".into(),
+                    default: Variant::new(),
+                    hint: PropertyHint::None,
+                    usage: PropertyUsage::DEFAULT,
+                },
+            ],
+        });
+        builder.add_signal(Signal {
+            name: Self::STATUSES_CHANGED.into(),
+            args: &[SignalArgument {
+                name: "statuses".into(),
+                default: Variant::new(),
+                hint: PropertyHint::None,
+                usage: PropertyUsage::DEFAULT,
+            }],
         });
     }
 }
 
 impl Player {
+    /// Adds to the inventory, spilling into the stash. Returns whatever neither could hold.
+    pub fn receive(&mut self, stacks: Vec<items::Stack>) -> Result<(), Vec<items::Stack>> {
+        let received = match self.inventory.attempt_add(stacks) {
+            Ok(()) => Ok(()),
+            Err(items::Error::Full(overflow)) => {
+                log::info!("Inventory is full, stashing {:?}.", overflow);
+                self.receive_into_stash(overflow)
+            },
+            Err(e) => {
+                log::warn!("Could not receive items due to {:?}.", e);
+                Err(vec![])
+            },
+        };
+        self.record_held();
+        received
+    }
+
+    /// Remembers everything held right now for discovery, so it still counts once used up.
+    /// Called whenever items land in the inventory or stash.
+    pub fn record_held(&mut self) {
+        let held = self.inventory.stacks().chain(self.stash.stacks()).map(|stack| stack.item.clone());
+        self.held_ever.extend(held);
+    }
+
+    /// Whether `item` has been held at any point during the run, including right now.
+    pub fn has_held(&self, item: &str) -> bool {
+        self.held_ever.contains(item)
+            || self.inventory.stacks().chain(self.stash.stacks()).any(|stack| stack.item == item)
+    }
+
+    /// Heals through the health system, so the hp signals fire. Returns the hp after healing.
+    pub fn heal(&mut self, owner: KinematicBody2D, amount: f64) -> f64 {
+        self.health.heal(amount, Some(unsafe { owner.to_object() }))
+    }
+
+    pub fn max_hp(&self) -> f64 {
+        self.health.max_hp()
+    }
+
+    /// Whether the player took any damage since the last call.
+    pub fn take_hurt(&mut self) -> bool {
+        self.health.take_hurt()
+    }
+
+    /// Everything the player would walk away with if the run ended now.
+    pub fn held_loot(&self) -> Vec<items::Stack> {
+        let equipped = Slot::ALL_SLOTS
+            .iter()
+            .filter_map(|&slot| self.equipment.get(slot))
+            .map(|item| items::Stack { item: item.clone(), count: 1 });
+        self.inventory
+            .stacks()
+            .chain(self.stash.stacks())
+            .cloned()
+            .chain(equipped)
+            .collect()
+    }
+
+    /// Grants the starting bonuses bought in previous runs.
+    fn apply_profile(&mut self, owner: KinematicBody2D) {
+        let profile = if let Some(profile) = Profile::get_autoload(unsafe { owner.to_node() }) {
+            profile
+        } else {
+            log::warn!("No profile found, starting without bonuses.");
+            return;
+        };
+        let bonuses = profile.map_mut(|profile, _| {
+            profile
+                .owned_bonuses()
+                .map(|(id, bonus)| (id.clone(), bonus.clone()))
+                .collect::<Vec<_>>()
+        });
+        let bonuses = match bonuses {
+            Ok(bonuses) => bonuses,
+            Err(e) => {
+                log::error!("Could not read profile due to {:?}.", e);
+                return;
+            },
+        };
+        for (id, bonus) in bonuses {
+            log::info!("Applying unlock {:?}.", id);
+            match bonus {
+                Bonus::StartingItems { items } => if let Err(lost) = self.receive(items) {
+                    log::warn!("Could not hold starting items {:?}.", lost);
+                },
+                Bonus::StartingModifiers { modifiers } => self.stats.add_source(Source::Perk(id), modifiers),
+                Bonus::Recipes { .. } => (),
+            }
+        }
+        self.apply_stats(owner);
+    }
+
+    fn receive_into_stash(&mut self, stacks: Vec<items::Stack>) -> Result<(), Vec<items::Stack>> {
+        self.stash
+            .attempt_add(stacks)
+            .map_err(|e| {
+                log::warn!("Stash is full as well: {:?}.", e);
+                e.into_overflow().unwrap_or_default()
+            })
+    }
+
     fn handle_mouse_button(&mut self, event: InputEventMouseButton, owner: KinematicBody2D) {
         const BUTTON_L: i64 = 1;
         const BUTTON_R: i64 = 2;
 
+        if self.health.statuses.is_stunned() {
+            log::info!("Stunned, ignoring mouse button.");
+            return;
+        }
+
         let button = event.get_button_index();
         let pressed = event.is_pressed();
 
@@ -148,7 +299,10 @@ impl Player {
                 BUTTON_L => if self.dash.is_dashing() {
                     // Do nothing
                 } else if self.aim.is_aiming() {
-                    self.aim.shoot(own_pos, unsafe { owner.to_node() }, self.calc_projectile_dmg());
+                    let dmg = self.calc_projectile_dmg();
+                    // Ammo that spilled into the stash is still the player's to fire.
+                    let ammo: &mut [&mut Inventory] = &mut [&mut self.inventory, &mut self.stash];
+                    self.aim.shoot(own_pos, unsafe { owner.to_node() }, dmg, Some(ammo));
                 },
                 _ => (),
             }
@@ -195,16 +349,121 @@ impl Player {
         }
     }
 
+    fn capture_base_stats(&mut self) {
+        self.stats.set_base(Stat::MaxHp, self.health.max_hp());
+        self.stats.set_base(Stat::MaxShield, self.health.max_shield());
+        self.stats.set_base(Stat::MoveSpeed, self.base_speed);
+        self.stats.set_base(Stat::DashSpeed, self.dash.cfg.speed);
+        self.stats.set_base(Stat::DashChain, self.dash.cfg.chain as f64);
+        self.stats.set_base(Stat::ProjectileDmg, self.aim.calc_dmg());
+        self.stats.set_base(Stat::MeleeDmg, self.melee.cfg.dmg_scale);
+        self.stats.set_base(Stat::Armor, self.health.cfg.armor());
+        for &kind in DamageType::ALL_TYPES {
+            self.stats.set_base(Stat::resist(kind), self.health.cfg.resistance(kind));
+        }
+    }
+    /// Pushes the effective stats into each system. Called whenever a source
+    /// of modifiers is added or removed.
+    fn apply_stats(&mut self, owner: KinematicBody2D) {
+        let max_hp = self.stats.effective(Stat::MaxHp);
+        if max_hp != self.health.max_hp() {
+            self.health.set_max_hp(max_hp, Some(unsafe { owner.to_object() }));
+        }
+        let max_shield = self.stats.effective(Stat::MaxShield);
+        if max_shield != self.health.max_shield() {
+            self.health.set_max_shield(max_shield, Some(unsafe { owner.to_object() }));
+        }
+        self.base_speed = self.stats.effective(Stat::MoveSpeed);
+        self.dash.cfg.speed = self.stats.effective(Stat::DashSpeed);
+        self.dash.cfg.chain = self.stats.effective(Stat::DashChain).round().max(0.) as u8;
+        self.aim.set_dmg(self.stats.effective(Stat::ProjectileDmg));
+        self.melee.cfg.dmg_scale = self.stats.effective(Stat::MeleeDmg);
+        self.health.cfg.set_armor(self.stats.effective(Stat::Armor));
+        for &kind in DamageType::ALL_TYPES {
+            self.health.cfg.set_resistance(kind, self.stats.effective(Stat::resist(kind)));
+        }
+    }
+    fn apply_effect(&mut self, owner: KinematicBody2D, source: &Source, effect: &Effect) {
+        match *effect {
+            Effect::Heal { amount } => {
+                self.health.heal(amount, Some(unsafe { owner.to_object() }));
+            },
+            Effect::RaiseMaxHp { amount } => {
+                self.stats.extend_source(
+                    Source::Perk(Self::CONSUMABLE_PERK.to_owned()),
+                    Modifier { stat: Stat::MaxHp, add: amount, mul: 0. },
+                );
+                self.apply_stats(owner);
+                self.health.heal(amount, Some(unsafe { owner.to_object() }));
+            },
+            Effect::Overheal { amount } => {
+                self.health.overheal(amount, Some(unsafe { owner.to_object() }));
+            },
+            Effect::Invincibility { millis } => {
+                self.health.set_invincibility(Duration::from_millis(millis));
+            },
+            Effect::SpeedBuff { multiplier, millis } => {
+                self.stats.add_source(
+                    source.clone(),
+                    vec![Modifier { stat: Stat::MoveSpeed, add: 0., mul: multiplier - 1. }],
+                );
+                self.buffs.retain(|(buff, _)| buff != source);
+                self.buffs.push((source.clone(), Duration::from_millis(millis)));
+                self.apply_stats(owner);
+            },
+            Effect::RefillDash => {
+                self.dash.refill();
+            },
+            Effect::Status { ref status } => {
+                self.health.statuses.apply(status, None);
+            },
+        }
+    }
+    fn process_buffs(&mut self, owner: KinematicBody2D, delta: Duration) {
+        let mut expired = vec![];
+        self.buffs.retain(|(source, remaining)| if *remaining > delta {
+            true
+        } else {
+            expired.push(source.clone());
+            false
+        });
+        for (_, remaining) in self.buffs.iter_mut() {
+            *remaining -= delta;
+        }
+        if expired.len() != 0 {
+            for source in expired.iter() {
+                log::info!("Buff {:?} wore off.", source);
+                self.stats.remove_source(source);
+            }
+            self.apply_stats(owner);
+        }
+    }
+
     fn calc_projectile_dmg(&self) -> f64 {
         self.aim.calc_dmg()
     }
     fn calc_vel(&mut self, delta: Duration) -> na::Vector2<f64> {
+        if self.health.statuses.is_stunned() {
+            return na::Vector2::zeros();
+        }
         // TODO Change `to_na_vec` to `into` when able to.
         // TODO Add an "acceleration" time.
         self.dash.calc_vel(delta)
             .or_else(|| self.aim.calc_vel(self.facing_dir))
             .or_else(|| self.melee.calc_vel(self.facing_dir))
             .unwrap_or_else(|| self.facing_dir.to_na_vec() * self.base_speed)
+            * self.health.statuses.speed_factor()
+    }
+    fn die(&self, mut owner: KinematicBody2D) {
+        // TODO Any other cleanup.
+        let cause = self.health.killer().map_or_else(Variant::new, DamageSource::to_variant);
+        log::info!("Player was killed by {:?}.", self.health.killer());
+        // Listeners cannot lock the player while it is dying, so hand over the loot now.
+        let loot = self.held_loot().to_variant();
+        unsafe {
+            owner.emit_signal("died".into(), &[cause, loot]);
+            owner.queue_free();
+        }
     }
 }
 
@@ -218,6 +477,9 @@ impl Player {
     unsafe fn _ready(&mut self, owner: KinematicBody2D) {
         self.aim.load_cache();
         self.melee.load_cache();
+        self.capture_base_stats();
+        self.apply_profile(owner);
+        // After the profile, so the run starts at whatever maximum its modifiers give.
         self.health.init();
         Group::Player.add_node(owner.to_node());
 
@@ -225,10 +487,26 @@ impl Player {
     }
 
     #[export]
-    fn _process(&mut self, owner: KinematicBody2D, delta: f64) {
+    fn _process(&mut self, mut owner: KinematicBody2D, delta: f64) {
         let delta = Duration::from_secs_f64(delta);
+        // Already dying, waiting on the queued free.
+        if self.health.is_dead() {
+            return;
+        }
         self.melee.process(delta);
-        self.health.process(delta);
+        self.health.process(delta, Some(unsafe { owner.to_object() }));
+        if self.health.is_dead() {
+            self.die(owner);
+            return;
+        }
+        if self.health.statuses.take_changed() {
+            let statuses = self.health.statuses.summary().to_variant();
+            unsafe { owner.emit_signal(Self::STATUSES_CHANGED.into(), &[statuses]) };
+        }
+        self.process_buffs(owner, delta);
+        if self.health.statuses.is_stunned() && self.aim.is_aiming() {
+            self.aim.reset(unsafe { owner.to_node() });
+        }
         self.aim.narrow_aim(unsafe { owner.to_node() }, delta);
     }
 
@@ -261,17 +539,19 @@ impl Player {
         let mut owner = unsafe { owner.to_object() };
         self.health.broadcast_max_hp(&mut owner);
         self.health.broadcast_hp(&mut owner);
+        self.health.broadcast_max_shield(&mut owner);
+        self.health.broadcast_layers(&mut owner);
     }
 
     #[export]
-    fn damage(&mut self, mut owner: KinematicBody2D, dmg: f64) {
+    fn damage(&mut self, owner: KinematicBody2D, dmg: Damage) {
+        // Already dying, waiting on the queued free.
+        if self.health.is_dead() {
+            return;
+        }
         self.health.damage(dmg, Some(unsafe { owner.to_object() }));
         if self.health.is_dead() {
-            // TODO Any other cleanup.
-            unsafe {
-                owner.emit_signal("died".into(), &[]);
-                owner.queue_free();
-            }
+            self.die(owner);
         }
     }
 
@@ -281,32 +561,85 @@ impl Player {
         log::info!("Reset facing direction.");
     }
 
+    /// Moves as much of `item` as fits from the stash back into the inventory.
     #[export]
-    fn craft_recipe(&mut self, _owner: KinematicBody2D, recipe: RecipeVariant) {
-        let recipe = Recipes::from(recipe);
-        recipe
-            .attempt_craft(&mut self.inventory)
-            .tap_err(|e| log::error!("Failed to craft recipe {:?} due to {:?}.", recipe, e))
-            .tap_ok(|e| log::info!("Successfully crafted {:?}!", recipe));
+    fn retrieve_from_stash(&mut self, _owner: KinematicBody2D, item: items::ItemId) {
+        let held = self.stash.count_items(&item);
+        let stack = match self.stash.attempt_take(item.clone(), held) {
+            Ok(stack) => stack,
+            Err(e) => {
+                log::warn!("Could not retrieve {:?} from the stash due to {:?}.", item, e);
+                return;
+            },
+        };
+        if let Err(e) = self.inventory.attempt_add(vec![stack]) {
+            log::info!("Inventory is full, returning the rest of {:?} to the stash.", item);
+            if let Some(overflow) = e.into_overflow() {
+                if let Err(lost) = self.receive_into_stash(overflow) {
+                    log::error!("Lost {:?} while returning it to the stash!", lost);
+                }
+            }
+        }
     }
 
     #[export]
-    fn use_item(&mut self, owner: KinematicBody2D, item: items::Item) {
-        if item.can_use {
-            if let Ok(stack) = self.inventory.attempt_take(item, 1) {
-                log::info!("Using item {:?}.", stack);
-                match stack.item.name.as_str() {
-                    "cheap health potion" => {
-                        self.health.heal(10., Some(unsafe { owner.to_object() }));
-                    },
-                    "health elixir" => {
-                        self.health.bump_max(10., Some(unsafe { owner.to_object() }));
-                    },
-                    _ => {
-                        log::warn!("Item {:?} has no effect!", stack.item);
-                    }
-                }
+    fn use_item(&mut self, owner: KinematicBody2D, item: items::ItemId) {
+        let effects = match items::registry().get(&item) {
+            Some(def) if def.can_use => &def.effects,
+            _ => return,
+        };
+        if effects.len() == 0 {
+            log::warn!("Item {:?} has no effect!", item);
+            return;
+        }
+        let mut transaction = self.inventory.transaction();
+        transaction.take(item.clone(), 1);
+        if let Err(e) = transaction.commit() {
+            log::warn!("Attempted to use item {:?}, but could not take it due to {:?}.", item, e);
+            return;
+        }
+        log::info!("Using item {:?}.", item);
+        let source = Source::Buff(item);
+        for effect in effects {
+            self.apply_effect(owner, &source, effect);
+        }
+    }
+
+    #[export]
+    fn equip_item(&mut self, owner: KinematicBody2D, item: items::ItemId) {
+        match self.equipment.equip(&mut self.inventory, item.clone()) {
+            Ok(Some(previous)) => log::info!("Equipped {:?}, replacing {:?}.", item, previous),
+            Ok(None) => log::info!("Equipped {:?}.", item),
+            Err(e) => {
+                log::warn!("Could not equip {:?} due to {:?}.", item, e);
+                return;
+            },
+        }
+        if let Some(def) = items::registry().get(&item) {
+            if let Some(slot) = def.slot {
+                self.stats.add_source(Source::Equipment(slot), def.modifiers.clone());
+                self.apply_stats(owner);
             }
         }
     }
+
+    #[export]
+    fn unequip_item(&mut self, owner: KinematicBody2D, slot: String) {
+        let slot = match Slot::from_name(&slot) {
+            Some(slot) => slot,
+            None => {
+                log::warn!("Attempted to unequip unknown slot {:?}.", slot);
+                return;
+            },
+        };
+        match self.equipment.unequip(&mut self.inventory, slot) {
+            Ok(item) => log::info!("Unequipped {:?} from {}.", item, slot.name()),
+            Err(e) => {
+                log::warn!("Could not unequip {} due to {:?}.", slot.name(), e);
+                return;
+            },
+        }
+        self.stats.remove_source(&Source::Equipment(slot));
+        self.apply_stats(owner);
+    }
 }
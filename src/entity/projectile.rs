@@ -16,7 +16,7 @@ use crate::{
     systems::{
         self,
         EditorCfg,
-        health::System as HealthSys,
+        health::{System as HealthSys, Damage, DamageType, Source},
     },
 };
 
@@ -24,6 +24,8 @@ pub struct Cfg {
     velocity: f64,
     max_bounces: u64,
     target_groups: StringArray,
+    /// Status effects applied to whatever is hit.
+    statuses: StringArray,
 }
 
 impl Cfg {
@@ -31,12 +33,19 @@ impl Cfg {
     const MAX_BOUNCES: u64 = 0;
 }
 
+impl Cfg {
+    fn statuses(&self) -> Vec<String> {
+        (0..self.statuses.len()).map(|i| self.statuses.get(i).to_string()).collect()
+    }
+}
+
 impl Default for Cfg {
     fn default() -> Self {
         Self {
             velocity: Self::VELOCITY,
             max_bounces: Self::MAX_BOUNCES,
             target_groups: StringArray::new(),
+            statuses: StringArray::new(),
         }
     }
 }
@@ -82,6 +91,16 @@ impl EditorCfg for Cfg {
             setter: move |this: &mut T, max| get_mut(this).max_bounces = max,
             usage: *systems::DEFAULT_USAGE,
         });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "statuses",
+            default: StringArray::new(),
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).statuses.new_ref(),
+            setter: move |this: &mut T, statuses| get_mut(this).statuses = statuses,
+            usage: *systems::DEFAULT_USAGE,
+        });
     }
 }
 
@@ -89,6 +108,7 @@ pub struct Normal {
     cfg: Cfg,
     dir: na::Vector2<f64>,
     dmg: f64,
+    source: Option<Source>,
 }
 
 impl Default for Normal {
@@ -97,6 +117,7 @@ impl Default for Normal {
             cfg: Default::default(),
             dir: na::Vector2::zeros(),
             dmg: Default::default(),
+            source: None,
         }
     }
 }
@@ -119,14 +140,27 @@ impl godot::NativeClass for Normal {
 }
 
 impl Normal {
-    fn inflict(&self, _owner: KinematicBody2D, target: Object) {
+    pub const ATTACK: &'static str = "normal_projectile";
+
+    /// Hits are credited to the shooter, or to the projectile itself if it was never fired.
+    fn source(&self, owner: KinematicBody2D) -> Source {
+        self.source
+            .clone()
+            .unwrap_or_else(|| Source::of(unsafe { owner.to_node() }, Self::ATTACK))
+    }
+
+    fn inflict(&self, owner: KinematicBody2D, target: Object) {
         if let Some(target) = unsafe { target.cast::<Node>() } {
             log::info!("Projectile collided with {:?}.", unsafe { target.get_name() });
             let groups = unsafe { target.get_groups() };
             for target_group in 0..self.cfg.target_groups.len() {
                 let target_group = self.cfg.target_groups.get(target_group);
                 if groups.contains(&target_group.to_variant()) {
-                    HealthSys::call_damage(unsafe { target.to_object() }, self.dmg);
+                    HealthSys::call_damage(
+                        unsafe { target.to_object() },
+                        Damage::new(self.dmg, DamageType::Projectile, self.source(owner))
+                            .with_statuses(self.cfg.statuses()),
+                    );
                     break;
                 }
             }
@@ -167,9 +201,11 @@ impl Normal {
         pos: na::Vector2<f64>,
         dir: na::Vector2<f64>,
         dmg: f64,
+        source: Source,
     ) {
         self.dir = dir;
         self.dmg = dmg;
+        self.source = Some(source);
         unsafe { owner.set_global_position(conv::na64_to_g(pos)) };
     }
 }
@@ -179,6 +215,7 @@ pub struct Charged {
     dir: na::Vector2<f64>,
     dmg: f64,
     remaining_bounces: u64,
+    source: Option<Source>,
 }
 
 impl Default for Charged {
@@ -188,6 +225,7 @@ impl Default for Charged {
             dir: na::Vector2::zeros(),
             dmg: Default::default(),
             remaining_bounces: Default::default(),
+            source: None,
         }
     }
 }
@@ -210,12 +248,25 @@ impl NativeClass for Charged {
 }
 
 impl Charged {
-    fn inflict(&self, _owner: KinematicBody2D, target: Object) {
+    pub const ATTACK: &'static str = "charged_projectile";
+
+    /// Hits are credited to the shooter, or to the projectile itself if it was never fired.
+    fn source(&self, owner: KinematicBody2D) -> Source {
+        self.source
+            .clone()
+            .unwrap_or_else(|| Source::of(unsafe { owner.to_node() }, Self::ATTACK))
+    }
+
+    fn inflict(&self, owner: KinematicBody2D, target: Object) {
         if let Some(target) = unsafe { target.cast::<Node>() } {
             log::info!("Projectile collided with {}.", unsafe { target.get_name() }.to_string());
             if Group::Enemy.has_node(target) || Group::Switch.has_node(target) {
                 log::info!("Inflicting damage!");
-                HealthSys::call_damage(unsafe { target.to_object() }, self.dmg);
+                HealthSys::call_damage(
+                    unsafe { target.to_object() },
+                    Damage::new(self.dmg, DamageType::Projectile, self.source(owner))
+                        .with_statuses(self.cfg.statuses()),
+                );
             }
         }
     }
@@ -275,9 +326,11 @@ impl Charged {
         pos: na::Vector2<f64>,
         dir: na::Vector2<f64>,
         dmg: f64,
+        source: Source,
     ) {
         self.dir = dir;
         self.dmg = dmg;
+        self.source = Some(source);
         self.remaining_bounces = self.cfg.max_bounces;
 
         unsafe { owner.set_global_position(conv::na64_to_g(pos)) };
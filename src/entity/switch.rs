@@ -8,7 +8,7 @@ use gdnative::{
     user_data::MutexData,
     Variant,
 };
-use crate::{util::Group, systems::{self, EditorCfg,}};
+use crate::{util::Group, systems::{self, EditorCfg, health::Damage}};
 
 pub struct Cfg {
     target: NodePath,
@@ -114,7 +114,7 @@ impl Switch {
     }
 
     #[export]
-    fn damage(&self, owner: StaticBody2D, _dmg: f64) {
+    fn damage(&self, owner: StaticBody2D, _dmg: Damage) {
         self.switch(owner);
     }
 }
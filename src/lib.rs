@@ -5,6 +5,7 @@ mod entity;
 
 mod event_bus;
 mod records;
+mod profile;
 
 mod ui;
 
@@ -80,6 +81,7 @@ fn init(handle: gdnative::init::InitHandle) {
     handle.add_class::<entity::Arena>();
     handle.add_class::<entity::Switch>();
     handle.add_class::<entity::Forge>();
+    handle.add_class::<entity::Pickup>();
 
     // Screens
     handle.add_class::<ui::UI>();
@@ -91,6 +93,7 @@ fn init(handle: gdnative::init::InitHandle) {
 
     handle.add_class::<event_bus::EventBus>();
     handle.add_class::<records::Records>();
+    handle.add_class::<profile::Profile>();
 }
 
 godot_gdnative_init!();
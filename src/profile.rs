@@ -0,0 +1,257 @@
+use gdnative::{
+    init::{ClassBuilder, Property, PropertyHint, PropertyUsage},
+    GodotString,
+    Instance,
+    NativeClass,
+    Node,
+    user_data::MutexData,
+};
+use serde::{Serialize, Deserialize};
+use std::{collections::{BTreeMap, HashSet}, fs::File};
+use tap::TapResultOps;
+
+use crate::{
+    systems::{items::{self, ItemId, Stack}, stats::Modifier},
+    util::{error, path_ops},
+};
+
+/// What an unlock grants in every run after it is bought.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Debug, Clone)]
+pub enum Bonus {
+    StartingItems { items: Vec<Stack> },
+    StartingModifiers { modifiers: Vec<Modifier> },
+    /// Recipes producing these items stay hidden until unlocked.
+    Recipes { items: Vec<ItemId> },
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Unlock {
+    pub name: String,
+    pub desc: String,
+    pub cost: u64,
+    pub bonus: Bonus,
+}
+
+/// Everything that is written to disk.
+#[derive(Serialize, Deserialize)]
+#[derive(Default, Debug)]
+struct Save {
+    bank: u64,
+    #[serde(default)]
+    unlocked: HashSet<String>,
+    /// Recipes learned from blueprints or bought outright.
+    #[serde(default)]
+    known_recipes: HashSet<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownUnlock(String),
+    AlreadyUnlocked(String),
+    CannotAfford { cost: u64, bank: u64 },
+    Save(error::JsonIOError),
+}
+
+impl From<error::JsonIOError> for Error {
+    fn from(e: error::JsonIOError) -> Self {
+        Self::Save(e)
+    }
+}
+
+/// Progress kept between runs: banked currency and the unlocks bought with it.
+pub struct Profile {
+    save_path: String,
+    unlock_path: String,
+    bank_share: f64,
+    save: Save,
+    unlocks: BTreeMap<String, Unlock>,
+}
+
+impl Profile {
+    const SAVE_PATH: &'static str = "user://profile.json";
+    const UNLOCK_PATH: &'static str = "res://profile/unlocks.json";
+    const BANK_SHARE: f64 = 0.1;
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            save_path: Self::SAVE_PATH.to_owned(),
+            unlock_path: Self::UNLOCK_PATH.to_owned(),
+            bank_share: Self::BANK_SHARE,
+            save: Default::default(),
+            unlocks: Default::default(),
+        }
+    }
+}
+
+impl NativeClass for Profile {
+    type Base = Node;
+    type UserData = MutexData<Profile>;
+
+    fn class_name() -> &'static str {
+        "Profile"
+    }
+
+    fn init(owner: Self::Base) -> Self {
+        Self::_init(owner)
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        builder.add_property(Property {
+            name: "save_path",
+            default: Self::SAVE_PATH.into(),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.save_path.clone().into(),
+            setter: |this: &mut Self, path: GodotString| this.save_path = path.to_string(),
+            usage: PropertyUsage::DEFAULT,
+        });
+        builder.add_property(Property {
+            name: "unlock_path",
+            default: Self::UNLOCK_PATH.into(),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.unlock_path.clone().into(),
+            setter: |this: &mut Self, path: GodotString| this.unlock_path = path.to_string(),
+            usage: PropertyUsage::DEFAULT,
+        });
+        builder.add_property(Property {
+            name: "bank_share",
+            default: Self::BANK_SHARE,
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.bank_share,
+            setter: |this: &mut Self, share| this.bank_share = share,
+            usage: PropertyUsage::DEFAULT,
+        });
+    }
+}
+
+impl Profile {
+    pub fn get_autoload(node: Node) -> Option<Instance<Self>> {
+        let profile = unsafe {
+            node.get_node("/root/Profile".into())?
+        };
+        Instance::<Profile>::try_from_base(profile)
+    }
+}
+
+impl Profile {
+    fn store(&self) -> Result<(), error::JsonIOError> {
+        let path = path_ops::abs_asset(self.save_path.clone());
+        let f = File::create(&path)?;
+        json::to_writer_pretty(f, &self.save)?;
+        Ok(())
+    }
+
+    pub fn bank(&self) -> u64 {
+        self.save.bank
+    }
+
+    /// Banks a share of the total value of `loot`, returning how much was added.
+    pub fn bank_loot<'a>(&mut self, loot: impl Iterator<Item = &'a Stack>) -> u64 {
+        let value: u64 = loot
+            .map(|stack| items::registry().value_of(&stack.item) * stack.count)
+            .sum();
+        let banked = (value as f64 * self.bank_share).floor() as u64;
+        self.save.bank += banked;
+        log::info!("Banked {} of a run worth {}.", banked, value);
+        self.store()
+            .tap_err(|e| log::error!("Could not save profile due to {:?}!", e))
+            .ok();
+        banked
+    }
+
+    pub fn unlocks(&self) -> impl Iterator<Item = (&String, &Unlock)> {
+        self.unlocks.iter()
+    }
+
+    pub fn owns(&self, id: &str) -> bool {
+        self.save.unlocked.contains(id)
+    }
+
+    pub fn owned_bonuses(&self) -> impl Iterator<Item = (&String, &Bonus)> {
+        self.unlocks
+            .iter()
+            .filter(move |(id, _)| self.owns(id))
+            .map(|(id, unlock)| (id, &unlock.bonus))
+    }
+
+    /// Items are craftable unless an unlock that has not been bought gates them.
+    pub fn is_item_unlocked(&self, item: &str) -> bool {
+        !self.unlocks.iter().any(|(id, unlock)| match &unlock.bonus {
+            Bonus::Recipes { items } => !self.owns(id) && items.iter().any(|gated| gated == item),
+            _ => false,
+        })
+    }
+
+    pub fn knows_recipe(&self, id: &str) -> bool {
+        self.save.known_recipes.contains(id)
+    }
+
+    /// Remembers a recipe for every later run.
+    pub fn learn_recipe(&mut self, id: &str) {
+        if self.save.known_recipes.insert(id.to_owned()) {
+            log::info!("Learned recipe {:?}.", id);
+            self.store()
+                .tap_err(|e| log::error!("Could not save profile due to {:?}!", e))
+                .ok();
+        }
+    }
+
+    pub fn buy_recipe(&mut self, id: &str, cost: u64) -> Result<(), Error> {
+        if self.knows_recipe(id) {
+            return Err(Error::AlreadyUnlocked(id.to_owned()));
+        }
+        if cost > self.save.bank {
+            return Err(Error::CannotAfford { cost, bank: self.save.bank });
+        }
+        self.save.bank -= cost;
+        self.save.known_recipes.insert(id.to_owned());
+        self.store()?;
+        Ok(())
+    }
+
+    pub fn purchase(&mut self, id: &str) -> Result<(), Error> {
+        let cost = self.unlocks
+            .get(id)
+            .ok_or_else(|| Error::UnknownUnlock(id.to_owned()))?
+            .cost;
+        if self.owns(id) {
+            return Err(Error::AlreadyUnlocked(id.to_owned()));
+        }
+        if cost > self.save.bank {
+            return Err(Error::CannotAfford { cost, bank: self.save.bank });
+        }
+        self.save.bank -= cost;
+        self.save.unlocked.insert(id.to_owned());
+        self.store()?;
+        Ok(())
+    }
+}
+
+#[gdnative::methods]
+impl Profile {
+    fn _init(_owner: Node) -> Self {
+        Default::default()
+    }
+
+    #[export]
+    fn _ready(&mut self, _owner: Node) {
+        let unlock_path = path_ops::abs_asset(self.unlock_path.clone());
+        log::info!("Loading unlocks from {:?}.", unlock_path);
+        self.unlocks = File::open(&unlock_path)
+            .map_err(error::JsonIOError::IO)
+            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            .tap_err(|e| log::error!("Could not load unlocks due to {:?}!", e))
+            .unwrap_or_default();
+        let save_path = path_ops::abs_asset(self.save_path.clone());
+        log::info!("Loading profile from {:?}.", save_path);
+        self.save = File::open(&save_path)
+            .map_err(error::JsonIOError::IO)
+            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            .tap_err(|e| log::info!("Starting a fresh profile, could not load one due to {:?}.", e))
+            .unwrap_or_default();
+    }
+}
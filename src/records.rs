@@ -10,6 +10,11 @@ use gdnative::{
 #[derive(Debug, Clone)]
 pub struct Record {
     pub wave_num: u64,
+    pub seed: u64,
+    pub banked: u64,
+    pub kills: u64,
+    /// What landed the killing blow on the player.
+    pub cause: String,
 }
 
 #[derive(Default, Debug)]
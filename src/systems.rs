@@ -7,6 +7,11 @@ pub mod dash;
 pub mod health;
 
 pub mod items;
+pub mod drops;
+pub mod effects;
+pub mod status;
+pub mod equipment;
+pub mod stats;
 
 lazy_static::lazy_static! {
     pub static ref DEFAULT_USAGE: PropertyUsage = PropertyUsage::SCRIPT_VARIABLE | PropertyUsage::STORAGE | PropertyUsage::EDITOR;
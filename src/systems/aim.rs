@@ -19,8 +19,8 @@ use boolinator::Boolinator;
 use rand::distributions::{Distribution, Uniform};
 use std::{ops::RangeInclusive, sync::{Arc, Mutex}, time::Duration};
 use crate::{
-    util::{conv, Direction, common_matrices as common_mats},
-    systems::{self, System as SysTrait, EditorCfg},
+    util::{conv, Direction, common_matrices as common_mats, rng::{self, Stream}},
+    systems::{self, System as SysTrait, EditorCfg, health::Source, items::{Inventory, ItemId}},
     entity::{NormalProjectile, ChargedProjectile},
 };
 
@@ -34,6 +34,10 @@ pub struct Cfg {
     normal_projectile: GodotString,
     charged_projectile: GodotString,
 
+    normal_ammo: Option<ItemId>,
+    charged_ammo: Option<ItemId>,
+    fallback_to_normal: bool,
+
     dmg: f64,
     aim_range_off_rot: f64,
     world: NodePath,
@@ -64,6 +68,10 @@ impl Cfg {
     const NORMAL_PROJECTILE_PATH: &'static str = "res://projectile.tscn";
     const CHARGED_PROJECTILE_PATH: &'static str = "res://charged_projectile.tscn";
 
+    const NORMAL_AMMO: &'static str = "";
+    const CHARGED_AMMO: &'static str = "";
+    const FALLBACK_TO_NORMAL: bool = true;
+
     const UPPER_FAN: &'static str = "LowerFan";
     const LOWER_FAN: &'static str = "UpperFan";
     const UPPER_FAN_NORMAL_TEX_PATH: &'static str = "res://ufan_normal.tres";
@@ -77,6 +85,43 @@ impl Cfg {
     const WALK_SPEED: f64 = 20.;
 }
 
+impl Cfg {
+    /// An empty item id means the projectile does not use ammo.
+    fn ammo_from(item: GodotString) -> Option<ItemId> {
+        let item = item.to_string();
+        if item.is_empty() {
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Decides whether the shot goes out charged, normal, or not at all,
+    /// taking the ammo it needs from the first of `ammo` that holds it.
+    /// Without any inventories every shot is free.
+    fn load_shot(&self, is_charged: bool, ammo: Option<&mut [&mut Inventory]>) -> Option<bool> {
+        let ammo = if let Some(ammo) = ammo { ammo } else { return Some(is_charged); };
+        let mut take = |item: &Option<ItemId>| match item {
+            Some(item) => ammo.iter_mut().any(|inv| inv.attempt_take(item.clone(), 1).is_ok()),
+            None => true,
+        };
+        if is_charged {
+            if take(&self.charged_ammo) {
+                return Some(true);
+            } else if !self.fallback_to_normal {
+                log::info!("Out of charged ammo {:?}.", self.charged_ammo);
+                return None;
+            }
+        }
+        if take(&self.normal_ammo) {
+            Some(false)
+        } else {
+            log::info!("Out of ammo {:?}.", self.normal_ammo);
+            None
+        }
+    }
+}
+
 impl Default for Cfg {
     fn default() -> Self {
         Self {
@@ -92,6 +137,10 @@ impl Default for Cfg {
             normal_projectile: Self::NORMAL_PROJECTILE_PATH.into(),
             charged_projectile: Self::CHARGED_PROJECTILE_PATH.into(),
 
+            normal_ammo: None,
+            charged_ammo: None,
+            fallback_to_normal: Self::FALLBACK_TO_NORMAL,
+
             lower_fan: NodePath::from_str(Self::UPPER_FAN),
             upper_fan: NodePath::from_str(Self::LOWER_FAN),
             ufan_normal_texture: Self::UPPER_FAN_NORMAL_TEX_PATH.into(),
@@ -180,6 +229,37 @@ impl EditorCfg for Cfg {
             usage: *systems::DEFAULT_USAGE,
         });
 
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "aim/normal_ammo",
+            default: Self::NORMAL_AMMO.into(),
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).normal_ammo.clone().unwrap_or_default().into(),
+            setter: move |this: &mut T, item: GodotString| get_mut(this).normal_ammo = Self::ammo_from(item),
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "aim/charged_ammo",
+            default: Self::CHARGED_AMMO.into(),
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).charged_ammo.clone().unwrap_or_default().into(),
+            setter: move |this: &mut T, item: GodotString| get_mut(this).charged_ammo = Self::ammo_from(item),
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "aim/fallback_to_normal",
+            default: Self::FALLBACK_TO_NORMAL,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).fallback_to_normal,
+            setter: move |this: &mut T, fallback| get_mut(this).fallback_to_normal = fallback,
+            usage: *systems::DEFAULT_USAGE,
+        });
+
         let get = get_proto.clone();
         let get_mut = get_mut_proto.clone();
         builder.add_property(Property {
@@ -508,7 +588,7 @@ impl Data {
         log::info!("Time and max: {:?}, {:?}", aim_duration, cfg.max_aim_time);
         if aim_duration > Duration::from_millis(0) {
             let aim_distribution = Uniform::from(self.possible_angle_offsets(cfg, log::Level::Info));
-            let aim_off = aim_distribution.sample(&mut rand::thread_rng());
+            let aim_off = rng::with(Stream::Aim, |rng| aim_distribution.sample(rng));
             let rot_mat = common_mats::rotation(aim_off);
             rot_mat * ideal
         } else {
@@ -660,7 +740,13 @@ impl System {
         self.cfg.dmg
     }
 
-    pub fn shoot(&mut self, from: na::Vector2<f64>, owner: Node, dmg: f64) {
+    pub fn set_dmg(&mut self, dmg: f64) {
+        self.cfg.dmg = dmg;
+    }
+
+    /// Fires at the current aim. When `ammo` is provided, each shot takes its
+    /// ammo from it and the shot is dropped if none is left.
+    pub fn shoot(&mut self, from: na::Vector2<f64>, owner: Node, dmg: f64, ammo: Option<&mut [&mut Inventory]>) {
         self.set_fan_visibility(owner, false);
         let (cfg, cache, data) = match self.res_view_mut() {
             Ok(view) => view,
@@ -674,11 +760,16 @@ impl System {
             }
         };
         data.stage = Stage::Cooldown;
+        let is_charged = match cfg.load_shot(data.is_charged(), ammo) {
+            Some(is_charged) => is_charged,
+            None => return,
+        };
+        let source = Source::of(owner, if is_charged { ChargedProjectile::ATTACK } else { NormalProjectile::ATTACK });
         (|| {
             // Modify stage
             let direction = data.true_dir(cfg, from);
             // Init projectile
-            let projectile_base = if data.is_charged() {
+            let projectile_base = if is_charged {
                 &cache.charged_projectile_scene
             } else {
                 &cache.projectile_scene
@@ -693,7 +784,7 @@ impl System {
                     a KinematicBody2D, which is unexpected.",
                     unsafe { owner.get_name() }.to_string(),
                 ))?;
-            if data.is_charged() {
+            if is_charged {
                 let projectile: Instance<ChargedProjectile> = Instance::try_from_base(projectile)
                     .tap_none(|| log::warn!(
                         "Provided normal projectile scene does not have \
@@ -707,6 +798,7 @@ impl System {
                         from + 20. * direction,
                         direction,
                         dmg,
+                        source.clone(),
                     ))
                     .ok().tap_none(|| log::warn!(
                         "Failed to obtain lock on user data for projectile when {} attempted to fire!",
@@ -727,6 +819,7 @@ impl System {
                         from + 20. * direction,
                         direction,
                         dmg,
+                        source.clone(),
                     ))
                     .ok().tap_none(|| log::warn!(
                         "Failed to obtain lock on user data for projectile when {} attempted to fire!",
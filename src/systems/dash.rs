@@ -177,6 +177,12 @@ impl System {
             self.cfg.speed
         })
     }
+    /// Restores the full dash chain without interrupting the current dash.
+    pub fn refill(&mut self) {
+        if let Some(data) = self.data.as_mut() {
+            data.count = 0;
+        }
+    }
     pub fn is_dashing(&self) -> bool {
         self.data.is_some()
     }
@@ -0,0 +1,269 @@
+use serde::{Serialize, Deserialize};
+use rand::{Rng, distributions::{Distribution, Uniform}};
+use std::collections::HashMap;
+
+use crate::{systems::items::{ItemId, Stack}, util::rng::{self, Stream}};
+
+/// How the count of a drop grows as waves progress.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "curve", rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scaling {
+    /// Counts stay within the range regardless of the wave.
+    Flat,
+    /// Counts grow by `per_wave` of the range for every wave after the first.
+    Linear { per_wave: f64 },
+    /// Counts are multiplied by `rate` for every wave after the first.
+    Exponential { rate: f64 },
+    /// The original formula, `[wave + min * (wave - 1), max * wave)`.
+    Legacy,
+}
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Self::Flat
+    }
+}
+
+impl Scaling {
+    fn sample(&self, range: (u64, u64), wave: u64) -> u64 {
+        let (min, max) = (range.0.min(range.1), range.0.max(range.1));
+        let past_first = wave.saturating_sub(1) as f64;
+        let factor = match self {
+            Self::Flat => 1.,
+            Self::Linear { per_wave } => 1. + per_wave * past_first,
+            Self::Exponential { rate } => rate.powf(past_first),
+            Self::Legacy => {
+                let low = wave + min * wave.saturating_sub(1);
+                let high = max * wave;
+                if high <= low {
+                    return low;
+                }
+                return rng::with(Stream::Drops, |rng| Uniform::from(low..high).sample(rng));
+            },
+        };
+        let count = rng::with(Stream::Drops, |rng| Uniform::new_inclusive(min, max).sample(rng));
+        (count as f64 * factor.max(0.)).round() as u64
+    }
+}
+
+/// Waves in which an entry can drop. Both ends are inclusive.
+#[derive(Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Waves {
+    #[serde(default)]
+    pub from: u64,
+    #[serde(default)]
+    pub until: Option<u64>,
+}
+
+impl Waves {
+    pub fn contains(&self, wave: u64) -> bool {
+        self.from <= wave && self.until.map_or(true, |until| wave <= until)
+    }
+}
+
+/// Raises the weight of a choice for every roll it misses, once it has missed `after` in a row.
+/// Choices sharing an `id` share the streak.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pity {
+    pub id: String,
+    #[serde(default)]
+    pub after: u64,
+    pub bonus: f64,
+}
+
+/// Dry streaks per pity id. Lives for the whole run, across every table.
+#[derive(Default, Debug)]
+pub struct PityCounters {
+    misses: HashMap<String, u64>,
+}
+
+impl PityCounters {
+    fn bonus(&self, pity: &Pity) -> f64 {
+        let misses = self.misses.get(&pity.id).copied().unwrap_or(0);
+        misses.saturating_sub(pity.after) as f64 * pity.bonus
+    }
+
+    fn record(&mut self, pity: &Pity, hit: bool) {
+        let misses = self.misses.entry(pity.id.clone()).or_default();
+        if hit {
+            *misses = 0;
+        } else {
+            *misses += 1;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Debug, Clone)]
+pub enum Drop {
+    Item {
+        item: ItemId,
+        range: (u64, u64),
+        #[serde(default)]
+        scaling: Scaling,
+    },
+    Table {
+        table: DropTable,
+    },
+    Nothing,
+}
+
+impl Drop {
+    fn roll_into(&self, wave: u64, pity: &mut PityCounters, out: &mut Vec<Stack>) {
+        match self {
+            Self::Item { item, range, scaling } => {
+                let count = scaling.sample(*range, wave);
+                if count != 0 {
+                    out.push(Stack { item: item.clone(), count });
+                }
+            },
+            Self::Table { table } => table.roll_into(wave, pity, out),
+            Self::Nothing => (),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Guaranteed {
+    #[serde(flatten)]
+    pub drop: Drop,
+    #[serde(default)]
+    pub waves: Waves,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Choice {
+    #[serde(flatten)]
+    pub drop: Drop,
+    pub weight: f64,
+    #[serde(default)]
+    pub waves: Waves,
+    #[serde(default)]
+    pub pity: Option<Pity>,
+}
+
+/// Picks one of its choices by weight, `rolls` times.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Roll {
+    pub choices: Vec<Choice>,
+    pub rolls: u64,
+}
+
+impl Roll {
+    fn pick(&self, wave: u64, pity: &mut PityCounters) -> Option<&Drop> {
+        let eligible: Vec<_> = self.choices
+            .iter()
+            .filter(|choice| choice.waves.contains(wave))
+            .map(|choice| {
+                let bonus = choice.pity.as_ref().map_or(0., |p| pity.bonus(p));
+                (choice, (choice.weight + bonus).max(0.))
+            })
+            .collect();
+        let sum: f64 = eligible.iter().map(|(_, weight)| weight).sum();
+        if sum <= 0. {
+            return None;
+        }
+        let mut bucket = rng::with(Stream::Drops, |rng| rng.gen_range(0., sum));
+        let mut picked = None;
+        for (idx, (_, weight)) in eligible.iter().enumerate() {
+            if bucket < *weight {
+                picked = Some(idx);
+                break;
+            }
+            bucket -= weight;
+        }
+        // Float error can leave the bucket just past the last weight.
+        let picked = picked.unwrap_or(eligible.len() - 1);
+        for (idx, (choice, _)) in eligible.iter().enumerate() {
+            if let Some(p) = choice.pity.as_ref() {
+                pity.record(p, idx == picked);
+            }
+        }
+        Some(&eligible[picked].0.drop)
+    }
+}
+
+/// Guaranteed drops always land, then each roll picks one of its choices.
+#[derive(Serialize, Deserialize)]
+#[serde(from = "Format")]
+#[derive(Default, Debug, Clone)]
+pub struct DropTable {
+    #[serde(default)]
+    pub guaranteed: Vec<Guaranteed>,
+    #[serde(default)]
+    pub rolls: Vec<Roll>,
+}
+
+impl DropTable {
+    pub fn generate_drops(&self, wave: u64, pity: &mut PityCounters) -> Vec<Stack> {
+        let mut drops = vec![];
+        self.roll_into(wave, pity, &mut drops);
+        drops
+    }
+
+    fn roll_into(&self, wave: u64, pity: &mut PityCounters, out: &mut Vec<Stack>) {
+        for guaranteed in self.guaranteed.iter().filter(|g| g.waves.contains(wave)) {
+            guaranteed.drop.roll_into(wave, pity, out);
+        }
+        for roll in &self.rolls {
+            for _ in 0..roll.rolls {
+                if let Some(drop) = roll.pick(wave, pity) {
+                    drop.roll_into(wave, pity, out);
+                }
+            }
+        }
+    }
+}
+
+/// An entry of the original `[[[{item, range}, chance], ...], rolls]` format.
+#[derive(Deserialize)]
+struct LegacyDrop {
+    item: ItemId,
+    range: (u64, u64),
+}
+
+/// Every format a drop table file may be written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Format {
+    Current {
+        #[serde(default)]
+        guaranteed: Vec<Guaranteed>,
+        #[serde(default)]
+        rolls: Vec<Roll>,
+    },
+    Legacy(Vec<(Vec<(LegacyDrop, f64)>, u64)>),
+}
+
+impl From<Format> for DropTable {
+    fn from(f: Format) -> Self {
+        match f {
+            Format::Current { guaranteed, rolls } => Self { guaranteed, rolls },
+            Format::Legacy(groups) => Self {
+                guaranteed: vec![],
+                rolls: groups
+                    .into_iter()
+                    .map(|(choices, rolls)| Roll {
+                        choices: choices
+                            .into_iter()
+                            .map(|(LegacyDrop { item, range }, weight)| Choice {
+                                drop: Drop::Item { item, range, scaling: Scaling::Legacy },
+                                weight,
+                                waves: Default::default(),
+                                pity: None,
+                            })
+                            .collect(),
+                        rolls,
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
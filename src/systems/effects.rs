@@ -0,0 +1,50 @@
+use serde::{Serialize, Deserialize};
+
+/// Something that happens to the user of a consumable.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Heal {
+        amount: f64,
+    },
+    RaiseMaxHp {
+        amount: f64,
+    },
+    /// Temporary hp past the maximum that decays over time.
+    Overheal {
+        amount: f64,
+    },
+    Invincibility {
+        millis: u64,
+    },
+    SpeedBuff {
+        multiplier: f64,
+        millis: u64,
+    },
+    RefillDash,
+    /// Applies the status effect registered under this id.
+    Status {
+        status: String,
+    },
+}
+
+impl Effect {
+    /// The same effect, `factor` times as strong.
+    pub fn scaled(&self, factor: f64) -> Self {
+        match self {
+            Self::Heal { amount } => Self::Heal { amount: amount * factor },
+            Self::RaiseMaxHp { amount } => Self::RaiseMaxHp { amount: amount * factor },
+            Self::Overheal { amount } => Self::Overheal { amount: amount * factor },
+            Self::Invincibility { millis } => Self::Invincibility {
+                millis: (*millis as f64 * factor).round() as u64,
+            },
+            Self::SpeedBuff { multiplier, millis } => Self::SpeedBuff {
+                multiplier: 1. + (multiplier - 1.) * factor,
+                millis: *millis,
+            },
+            Self::RefillDash => Self::RefillDash,
+            Self::Status { status } => Self::Status { status: status.clone() },
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::systems::items::{self, Inventory, ItemId, Stack};
+
+const WEAPON: &'static str = "weapon";
+const ARMOR: &'static str = "armor";
+const TRINKET: &'static str = "trinket";
+
+/// Serialized under the same names as `name`, so data files, the UI and
+/// `from_name` all agree.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Trinket,
+}
+
+impl Slot {
+    pub const ALL_SLOTS: &'static [Self] = &[Self::Weapon, Self::Armor, Self::Trinket];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Weapon => WEAPON,
+            Self::Armor => ARMOR,
+            Self::Trinket => TRINKET,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL_SLOTS.iter().find(|slot| slot.name() == name).copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NotEquipment(ItemId),
+    EmptySlot(Slot),
+    Inventory(items::Error),
+}
+
+impl From<items::Error> for Error {
+    fn from(e: items::Error) -> Self {
+        Self::Inventory(e)
+    }
+}
+
+/// Items currently worn, at most one per slot.
+#[derive(Default, Debug, Clone)]
+pub struct Loadout {
+    equipped: HashMap<Slot, ItemId>,
+}
+
+impl Loadout {
+    pub fn get(&self, slot: Slot) -> Option<&ItemId> {
+        self.equipped.get(&slot)
+    }
+
+    /// Moves `item` out of the inventory and into its slot, returning whatever
+    /// was swapped out to the inventory.
+    pub fn equip(&mut self, inventory: &mut Inventory, item: ItemId) -> Result<Option<ItemId>, Error> {
+        let slot = items::registry()
+            .get(&item)
+            .and_then(|def| def.slot)
+            .ok_or_else(|| Error::NotEquipment(item.clone()))?;
+
+        let mut transaction = inventory.transaction();
+        transaction.take(item.clone(), 1);
+        if let Some(previous) = self.equipped.get(&slot) {
+            transaction.add(vec![Stack {
+                item: previous.clone(),
+                count: 1,
+            }]);
+        }
+        transaction.commit()?;
+
+        Ok(self.equipped.insert(slot, item))
+    }
+
+    /// Moves the item in `slot` back into the inventory.
+    pub fn unequip(&mut self, inventory: &mut Inventory, slot: Slot) -> Result<ItemId, Error> {
+        let item = self.equipped.get(&slot).cloned().ok_or(Error::EmptySlot(slot))?;
+
+        let mut transaction = inventory.transaction();
+        transaction.add(vec![Stack {
+            item: item.clone(),
+            count: 1,
+        }]);
+        transaction.commit()?;
+
+        self.equipped.remove(&slot);
+        Ok(item)
+    }
+}
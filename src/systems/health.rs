@@ -1,25 +1,174 @@
 use gdnative::{
+    FromVariant,
     NativeClass,
+    Node,
     init::{ClassBuilder, Property, PropertyHint, PropertyUsage, Signal, SignalArgument,},
     user_data::MutexData,
     Variant,
     Object,
+    ToVariant,
 };
-use std::time::{Duration};
-use crate::systems::{self, System as SysTrait, EditorCfg};
+use serde::{Serialize, Deserialize};
+use std::{collections::HashMap, time::{Duration}};
+use crate::{systems::{self, System as SysTrait, EditorCfg, status::Statuses}, util::Group};
+
+const MELEE: &'static str = "melee";
+const PROJECTILE: &'static str = "projectile";
+const CONTACT: &'static str = "contact";
+const ENVIRONMENTAL: &'static str = "environmental";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(ToVariant, FromVariant)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DamageType {
+    Melee,
+    Projectile,
+    Contact,
+    Environmental,
+}
+
+impl DamageType {
+    pub const ALL_TYPES: &'static [Self] = &[
+        Self::Melee,
+        Self::Projectile,
+        Self::Contact,
+        Self::Environmental,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Melee => MELEE,
+            Self::Projectile => PROJECTILE,
+            Self::Contact => CONTACT,
+            Self::Environmental => ENVIRONMENTAL,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL_TYPES.iter().find(|ty| ty.name() == name).copied()
+    }
+
+    fn resist_property(&self) -> &'static str {
+        match self {
+            Self::Melee => "health/resist/melee",
+            Self::Projectile => "health/resist/projectile",
+            Self::Contact => "health/resist/contact",
+            Self::Environmental => "health/resist/environmental",
+        }
+    }
+}
+
+#[derive(ToVariant, FromVariant)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Faction {
+    Player,
+    Enemy,
+    Neutral,
+}
+
+impl Faction {
+    /// The faction of a node, going by the groups it is in.
+    pub fn of(node: Node) -> Self {
+        if Group::Player.has_node(node) {
+            Self::Player
+        } else if Group::Enemy.has_node(node) {
+            Self::Enemy
+        } else {
+            Self::Neutral
+        }
+    }
+}
+
+/// Who dealt a hit and with what. The attacker may be freed by the time anyone reads this,
+/// so only its instance id and name are kept.
+#[derive(ToVariant, FromVariant)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub attacker_id: i64,
+    pub attacker: String,
+    /// The attack or projectile kind, such as `melee` or `charged_projectile`.
+    pub attack: String,
+    pub faction: Faction,
+}
+
+impl Source {
+    pub fn of(attacker: Node, attack: &str) -> Self {
+        Self {
+            attacker_id: unsafe { attacker.get_instance_id() },
+            attacker: unsafe { attacker.get_name() }.to_string(),
+            attack: attack.to_owned(),
+            faction: Faction::of(attacker),
+        }
+    }
+
+    /// Hits sharing a key share a hit cooldown.
+    fn key(&self) -> (i64, String) {
+        (self.attacker_id, self.attack.clone())
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} ({})", self.attacker, self.attack)
+    }
+}
+
+/// A single hit, before the target's resistances and armor are applied.
+#[derive(ToVariant, FromVariant)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Damage {
+    pub amount: f64,
+    pub kind: DamageType,
+    pub source: Source,
+    /// Ids of the status effects a landed hit applies.
+    pub statuses: Vec<String>,
+}
+
+impl Damage {
+    pub fn new(amount: f64, kind: DamageType, source: Source) -> Self {
+        Self { amount, kind, source, statuses: vec![] }
+    }
+
+    pub fn with_statuses(self, statuses: Vec<String>) -> Self {
+        Self { statuses, ..self }
+    }
+}
 
 #[derive(Debug)]
 pub struct Cfg {
     max_hp: f64,
     invincibility_on_damage: Duration,
+    /// How long each source has to wait before it can hurt again. Unlike invincibility,
+    /// other sources can still land hits in the meantime.
+    hit_cooldown: Duration,
+    /// Subtracted from every hit after resistances.
+    armor: f64,
+    /// Fraction of each damage type that is ignored. Negative values are weaknesses.
+    resistances: HashMap<DamageType, f64>,
+    /// Absorbs damage before hp. None by default.
+    max_shield: f64,
+    /// Shield restored per second once `shield_delay` passed without taking damage.
+    shield_regen: f64,
+    shield_delay: Duration,
+    /// Overheal lost per second.
+    overheal_decay: f64,
 }
 
 impl Cfg {
     const MAX_HP: f64 = 100.;
     const INVINCIBILITY_ON_DAMAGE: Duration = Duration::from_millis(0);
+    const HIT_COOLDOWN: Duration = Duration::from_millis(0);
+    const ARMOR: f64 = 0.;
+    const RESISTANCE: f64 = 0.;
+    const MAX_SHIELD: f64 = 0.;
+    const SHIELD_REGEN: f64 = 10.;
+    const SHIELD_DELAY: Duration = Duration::from_millis(3000);
+    const OVERHEAL_DECAY: f64 = 5.;
 
     const MAX_HP_SIGNAL: &'static str = "max_hp";
     const HP_SIGNAL: &'static str = "hp";
+    const MAX_SHIELD_SIGNAL: &'static str = "max_shield";
+    const SHIELD_SIGNAL: &'static str = "shield";
+    const OVERHEAL_SIGNAL: &'static str = "overheal";
 }
 
 impl Default for Cfg {
@@ -27,6 +176,13 @@ impl Default for Cfg {
         Cfg {
             max_hp: Self::MAX_HP,
             invincibility_on_damage: Self::INVINCIBILITY_ON_DAMAGE,
+            hit_cooldown: Self::HIT_COOLDOWN,
+            armor: Self::ARMOR,
+            resistances: HashMap::new(),
+            max_shield: Self::MAX_SHIELD,
+            shield_regen: Self::SHIELD_REGEN,
+            shield_delay: Self::SHIELD_DELAY,
+            overheal_decay: Self::OVERHEAL_DECAY,
         }
     }
 }
@@ -62,6 +218,78 @@ impl EditorCfg for Cfg {
             setter: move |this: &mut T, invincibility| get_mut(this).invincibility_on_damage = Duration::from_millis(invincibility),
             usage: *systems::DEFAULT_USAGE,
         });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/hit_cooldown",
+            default: Self::HIT_COOLDOWN.as_millis() as u64,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).hit_cooldown.as_millis() as u64,
+            setter: move |this: &mut T, cooldown| get_mut(this).hit_cooldown = Duration::from_millis(cooldown),
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/armor",
+            default: Self::ARMOR,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).armor,
+            setter: move |this: &mut T, armor| get_mut(this).armor = armor,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        for &kind in DamageType::ALL_TYPES {
+            let get = get_proto.clone();
+            let get_mut = get_mut_proto.clone();
+            builder.add_property(Property {
+                name: kind.resist_property(),
+                default: Self::RESISTANCE,
+                hint: PropertyHint::None,
+                getter: move |this: &T| get(this).resistance(kind),
+                setter: move |this: &mut T, resistance| get_mut(this).set_resistance(kind, resistance),
+                usage: *systems::DEFAULT_USAGE,
+            });
+        }
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/max_shield",
+            default: Self::MAX_SHIELD,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).max_shield,
+            setter: move |this: &mut T, shield| get_mut(this).max_shield = shield,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/shield_regen",
+            default: Self::SHIELD_REGEN,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).shield_regen,
+            setter: move |this: &mut T, regen| get_mut(this).shield_regen = regen,
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/shield_delay",
+            default: Self::SHIELD_DELAY.as_millis() as u64,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).shield_delay.as_millis() as u64,
+            setter: move |this: &mut T, delay| get_mut(this).shield_delay = Duration::from_millis(delay),
+            usage: *systems::DEFAULT_USAGE,
+        });
+        let get = get_proto.clone();
+        let get_mut = get_mut_proto.clone();
+        builder.add_property(Property {
+            name: "health/overheal_decay",
+            default: Self::OVERHEAL_DECAY,
+            hint: PropertyHint::None,
+            getter: move |this: &T| get(this).overheal_decay,
+            setter: move |this: &mut T, decay| get_mut(this).overheal_decay = decay,
+            usage: *systems::DEFAULT_USAGE,
+        });
         builder.add_signal(Signal {
             name: Self::MAX_HP_SIGNAL,
             args: &[SignalArgument {
@@ -80,24 +308,112 @@ impl EditorCfg for Cfg {
                 usage: PropertyUsage::DEFAULT,
             }],
         });
+        builder.add_signal(Signal {
+            name: Self::MAX_SHIELD_SIGNAL,
+            args: &[SignalArgument {
+                name: "max_shield",
+                default: Variant::from_u64((0.0 as f64).to_bits()),
+                hint: PropertyHint::None,
+                usage: PropertyUsage::DEFAULT,
+            }],
+        });
+        builder.add_signal(Signal {
+            name: Self::SHIELD_SIGNAL,
+            args: &[SignalArgument {
+                name: "shield",
+                default: Variant::from_u64((0.0 as f64).to_bits()),
+                hint: PropertyHint::None,
+                usage: PropertyUsage::DEFAULT,
+            }],
+        });
+        builder.add_signal(Signal {
+            name: Self::OVERHEAL_SIGNAL,
+            args: &[SignalArgument {
+                name: "overheal",
+                default: Variant::from_u64((0.0 as f64).to_bits()),
+                hint: PropertyHint::None,
+                usage: PropertyUsage::DEFAULT,
+            }],
+        });
     }
 }
 
 impl Cfg {
+    pub fn armor(&self) -> f64 {
+        self.armor
+    }
+    pub fn set_armor(&mut self, armor: f64) {
+        self.armor = armor;
+    }
+    pub fn resistance(&self, kind: DamageType) -> f64 {
+        self.resistances.get(&kind).copied().unwrap_or(Self::RESISTANCE)
+    }
+    pub fn set_resistance(&mut self, kind: DamageType, resistance: f64) {
+        self.resistances.insert(kind, resistance);
+    }
+
+    /// How much of `dmg` gets through. Resistances cap at fully immune and armor never heals.
+    pub fn mitigate(&self, dmg: &Damage) -> f64 {
+        (self.resist(dmg) - self.armor).max(0.)
+    }
+    /// `dmg` after resistances alone. Armor turns blows, not damage over time.
+    pub fn resist(&self, dmg: &Damage) -> f64 {
+        let resistance = self.resistance(dmg.kind).min(1.);
+        (dmg.amount * (1. - resistance)).max(0.)
+    }
+
     pub fn broadcast_max_hp(&self, broadcaster: &mut Object) {
         unsafe {
             broadcaster.emit_signal("max_hp".into(), &[Variant::from_u64(self.max_hp.to_bits())]);
         }
     }
+    pub fn broadcast_max_shield(&self, broadcaster: &mut Object) {
+        unsafe {
+            broadcaster.emit_signal(Self::MAX_SHIELD_SIGNAL.into(), &[Variant::from_u64(self.max_shield.to_bits())]);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Data {
     invincibility: Option<Duration>,
     hp: f64,
+    /// Time left until each source can hurt again.
+    hit_cooldowns: HashMap<(i64, String), Duration>,
+    shield: f64,
+    overheal: f64,
+    since_hurt: Duration,
+    /// Whether any damage got through since last asked.
+    hurt: bool,
 }
 
 impl Data {
+    /// Takes `dmg` out of the shield, then the overheal and only then the hp.
+    fn absorb(&mut self, mut dmg: f64) {
+        self.since_hurt = Duration::from_millis(0);
+        if dmg > 0. {
+            self.hurt = true;
+        }
+        let shielded = dmg.min(self.shield);
+        self.shield -= shielded;
+        dmg -= shielded;
+        let overhealed = dmg.min(self.overheal);
+        self.overheal -= overhealed;
+        dmg -= overhealed;
+        self.hp -= dmg;
+    }
+
+    /// Regenerates the shield and decays overheal. Returns whether either changed.
+    fn process_layers(&mut self, cfg: &Cfg, delta: Duration) -> bool {
+        let (shield, overheal) = (self.shield, self.overheal);
+        self.since_hurt += delta;
+        if self.since_hurt >= cfg.shield_delay {
+            self.shield = (self.shield + cfg.shield_regen * delta.as_secs_f64()).min(cfg.max_shield);
+        }
+        self.overheal = (self.overheal - cfg.overheal_decay * delta.as_secs_f64()).max(0.);
+        shield != self.shield || overheal != self.overheal
+    }
+
     fn process(&mut self, delta: Duration) {
         if let Some(inv) = self.invincibility.as_mut() {
             if *inv > delta {
@@ -106,6 +422,16 @@ impl Data {
                 self.invincibility = None;
             }
         }
+        self.hit_cooldowns.retain(|_, remaining| if *remaining > delta {
+            *remaining -= delta;
+            true
+        } else {
+            false
+        });
+    }
+
+    fn is_cooling_down(&self, source: &Source) -> bool {
+        self.hit_cooldowns.contains_key(&source.key())
     }
 }
 
@@ -114,6 +440,9 @@ pub struct System {
     pub cfg: Cfg,
     cache: (),
     pub data: Option<Data>,
+    /// Source of the last hit that got through, kept after death.
+    last_hit: Option<Source>,
+    pub statuses: Statuses,
 }
 
 impl System {
@@ -121,7 +450,13 @@ impl System {
         self.data = Some(Data {
             invincibility: None,
             hp: self.cfg.max_hp,
+            hit_cooldowns: HashMap::new(),
+            shield: self.cfg.max_shield,
+            overheal: 0.,
+            since_hurt: Duration::from_millis(0),
+            hurt: false,
         });
+        self.statuses.clear();
     }
     pub fn broadcast_hp(&self, broadcaster: &mut Object) {
         let hp = self.data.as_ref().map(|d| d.hp).unwrap_or(0.).to_bits();
@@ -133,9 +468,38 @@ impl System {
     pub fn broadcast_max_hp(&self, broadcaster: &mut Object) {
         self.cfg.broadcast_max_hp(broadcaster);
     }
+    pub fn broadcast_max_shield(&self, broadcaster: &mut Object) {
+        self.cfg.broadcast_max_shield(broadcaster);
+    }
+    /// Broadcasts the shield and overheal on top of the hp.
+    pub fn broadcast_layers(&self, broadcaster: &mut Object) {
+        let (shield, overheal) = self.data.as_ref().map_or((0., 0.), |d| (d.shield, d.overheal));
+        unsafe {
+            broadcaster.emit_signal(Cfg::SHIELD_SIGNAL.into(), &[Variant::from_u64(shield.to_bits())]);
+            broadcaster.emit_signal(Cfg::OVERHEAL_SIGNAL.into(), &[Variant::from_u64(overheal.to_bits())]);
+        }
+    }
 
-    pub fn process(&mut self, delta: Duration) {
-        self.data.as_mut().map(|data| data.process(delta));
+    /// Counts down timers, regenerates the shield, decays overheal and applies whatever
+    /// status effects tick.
+    pub fn process(&mut self, delta: Duration, mut to_notify: Option<Object>) {
+        let cfg = &self.cfg;
+        let layers_changed = self.data.as_mut().map_or(false, |data| {
+            data.process(delta);
+            data.process_layers(cfg, delta)
+        });
+        if layers_changed {
+            if let Some(target) = to_notify.as_mut() {
+                self.broadcast_layers(target);
+            }
+        }
+        for tick in self.statuses.process(delta) {
+            if tick.amount < 0. {
+                self.heal(-tick.amount, to_notify);
+            } else {
+                self.wear(tick, to_notify);
+            }
+        }
     }
 
     pub fn is_invincible(&self) -> bool {
@@ -148,22 +512,64 @@ impl System {
     pub fn is_dead(&self) -> bool {
         self.data.is_none()
     }
+    /// Whoever landed the final hit, once dead.
+    pub fn killer(&self) -> Option<&Source> {
+        if self.is_dead() {
+            self.last_hit.as_ref()
+        } else {
+            None
+        }
+    }
 
+    /// Whether any damage got through since the last call.
+    pub fn take_hurt(&mut self) -> bool {
+        self.data.as_mut().map_or(false, |data| std::mem::replace(&mut data.hurt, false))
+    }
     pub fn set_invincibility(&mut self, duration: Duration) {
         self.data.as_mut().map(|data| data.invincibility = Some(duration));
     }
-    pub fn damage(&mut self, dmg: f64, mut to_notify: Option<Object>) -> f64 {
-        if !self.is_invincible() {
+    pub fn damage(&mut self, dmg: Damage, mut to_notify: Option<Object>) -> f64 {
+        let cooling_down = self.data.as_ref().map_or(false, |data| data.is_cooling_down(&dmg.source));
+        if !self.is_invincible() && !cooling_down {
+            let dealt = self.cfg.mitigate(&dmg);
             if let Some(data) = self.data.as_mut() {
-                data.hp -= dmg;
+                data.absorb(dealt);
                 data.invincibility = Some(self.cfg.invincibility_on_damage);
+                if self.cfg.hit_cooldown > Duration::from_millis(0) {
+                    data.hit_cooldowns.insert(dmg.source.key(), self.cfg.hit_cooldown);
+                }
                 if data.hp <= 0. {
                     self.data = None;
+                } else {
+                    for status in dmg.statuses.iter() {
+                        self.statuses.apply(status, Some(dmg.source.clone()));
+                    }
                 }
+                self.last_hit = Some(dmg.source);
             }
             if let Some(target) = to_notify.as_mut() {
                 self.broadcast_hp(target);
+                self.broadcast_layers(target);
+            }
+        }
+        self.data.as_ref().map_or(
+            0.,
+            |data| data.hp,
+        )
+    }
+    /// Damage that ignores armor and invincibility, such as from status effects.
+    pub fn wear(&mut self, dmg: Damage, mut to_notify: Option<Object>) -> f64 {
+        let dealt = self.cfg.resist(&dmg);
+        if let Some(data) = self.data.as_mut() {
+            data.absorb(dealt);
+            if data.hp <= 0. {
+                self.data = None;
             }
+            self.last_hit = Some(dmg.source);
+        }
+        if let Some(target) = to_notify.as_mut() {
+            self.broadcast_hp(target);
+            self.broadcast_layers(target);
         }
         self.data.as_ref().map_or(
             0.,
@@ -185,23 +591,65 @@ impl System {
             |data| data.hp,
         )
     }
-    pub fn bump_max(&mut self, increase: f64, mut to_notify: Option<Object>) -> f64 {
-        self.cfg.max_hp += increase;
+    /// Adds temporary hp past the maximum, which decays over time. Capped at the maximum hp.
+    pub fn overheal(&mut self, amount: f64, mut to_notify: Option<Object>) -> f64 {
+        let max_hp = self.cfg.max_hp;
+        if let Some(data) = self.data.as_mut() {
+            data.overheal = (data.overheal + amount).min(max_hp).max(0.);
+        }
+        if let Some(target) = to_notify.as_mut() {
+            self.broadcast_layers(target);
+        }
+        self.data.as_ref().map_or(0., |data| data.overheal)
+    }
+    /// Tops the shield up by `amount`, up to its maximum.
+    pub fn restore_shield(&mut self, amount: f64, mut to_notify: Option<Object>) -> f64 {
+        let max_shield = self.cfg.max_shield;
+        if let Some(data) = self.data.as_mut() {
+            data.shield = (data.shield + amount).min(max_shield).max(0.);
+        }
+        if let Some(target) = to_notify.as_mut() {
+            self.broadcast_layers(target);
+        }
+        self.data.as_ref().map_or(0., |data| data.shield)
+    }
+    /// Changes the maximum shield, clamping the current shield to it. Like hp, an increase
+    /// is not restored, the shield regenerates into it instead.
+    pub fn set_max_shield(&mut self, max_shield: f64, mut to_notify: Option<Object>) -> f64 {
+        self.cfg.max_shield = max_shield.max(0.);
+        if let Some(data) = self.data.as_mut() {
+            data.shield = data.shield.min(self.cfg.max_shield);
+        }
+        if let Some(target) = to_notify.as_mut() {
+            self.broadcast_max_shield(target);
+            self.broadcast_layers(target);
+        }
+        self.cfg.max_shield
+    }
+    pub fn max_shield(&self) -> f64 {
+        self.cfg.max_shield
+    }
+    /// Changes the maximum hp, clamping the current hp to it. An increase is not healed.
+    pub fn set_max_hp(&mut self, max_hp: f64, mut to_notify: Option<Object>) -> f64 {
+        self.cfg.max_hp = max_hp;
         if let Some(target) = to_notify.as_mut() {
             self.broadcast_max_hp(target);
         }
-        self.heal(increase, to_notify);
+        self.heal(0., to_notify);
         self.cfg.max_hp
     }
 
     pub fn get_max_hp(&self) -> u64 {
         self.cfg.max_hp as u64
     }
+    pub fn max_hp(&self) -> f64 {
+        self.cfg.max_hp
+    }
 }
 
 impl System {
-    pub fn call_damage(mut target: Object, dmg: f64) {
-        unsafe { target.call("damage".into(), &[Variant::from_f64(dmg)]) };
+    pub fn call_damage(mut target: Object, dmg: Damage) {
+        unsafe { target.call("damage".into(), &[dmg.to_variant()]) };
     }
 }
 
@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs::File};
 use serde::{Serialize, Deserialize};
+use tap::TapResultOps;
 
 use godot::{ToVariant, FromVariant};
 
+use crate::{systems::{effects::Effect, equipment::Slot, stats::Modifier}, util::{error, path_ops}};
+
+/// Stable identifier of an item, as used in the registry and all data files.
+pub type ItemId = String;
+
 #[derive(Serialize, Deserialize)]
 #[derive(ToVariant, FromVariant)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -10,6 +16,7 @@ pub enum Category {
     Raw,
     Unique,
     Ammo,
+    Equipment,
 }
 
 impl Category {
@@ -17,109 +24,217 @@ impl Category {
         match self {
             Category::Raw => 999,
             Category::Unique => 1,
-            Category::Ammo => 0,
+            Category::Ammo => 99,
+            Category::Equipment => 1,
+        }
+    }
+}
+
+const NORMAL: &'static str = "normal";
+const FINE: &'static str = "fine";
+const MASTERWORK: &'static str = "masterwork";
+/// Separates an item id from its quality, as in `pixel_blade#fine`.
+const QUALITY_SEPARATOR: char = '#';
+
+/// How well a crafted item turned out. Better items have stronger effects and modifiers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Quality {
+    Normal,
+    Fine,
+    Masterwork,
+}
+
+impl Quality {
+    const FINE_FACTOR: f64 = 1.25;
+    const MASTERWORK_FACTOR: f64 = 1.5;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => NORMAL,
+            Self::Fine => FINE,
+            Self::Masterwork => MASTERWORK,
+        }
+    }
+
+    pub fn factor(&self) -> f64 {
+        match self {
+            Self::Normal => 1.,
+            Self::Fine => Self::FINE_FACTOR,
+            Self::Masterwork => Self::MASTERWORK_FACTOR,
+        }
+    }
+
+    /// The plain id of `item`, whatever its quality.
+    pub fn base_id(item: &str) -> &str {
+        item.split(QUALITY_SEPARATOR).next().unwrap_or(item)
+    }
+
+    /// Id of `item` made at this quality. Normal items keep their plain id.
+    pub fn tag(&self, item: &str) -> ItemId {
+        match self {
+            Self::Normal => item.to_owned(),
+            quality => format!("{}{}{}", item, QUALITY_SEPARATOR, quality.name()),
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
-#[derive(ToVariant, FromVariant)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Item {
     pub category: Category,
 
     pub name: String,
     pub desc: String,
     pub can_use: bool,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub slot: Option<Slot>,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    /// Worth of a single item when banking loot at the end of a run.
+    #[serde(default)]
+    pub value: u64,
 }
 
-impl Item {
+#[derive(Default, Debug)]
+pub struct Registry {
+    items: HashMap<ItemId, Item>,
 }
 
-#[derive(Serialize, Deserialize)]
-#[derive(ToVariant, FromVariant)]
-#[derive(Debug, Clone)]
-pub struct Drop {
-    pub item: Item,
-    pub range: (u64, u64),
-}
+impl Item {
+    fn has_quality(&self) -> bool {
+        self.effects.len() != 0 || self.modifiers.len() != 0
+    }
 
-impl Drop {
-    fn generate_drop(&self, wave: u64) -> Stack {
-        use rand::distributions::{Distribution, Uniform};
-        Stack {
-            item: self.item.clone(),
-            count: Uniform::from((wave + self.range.0 * (wave - 1))..(self.range.1*wave)).sample(&mut rand::thread_rng()),
+    fn with_quality(&self, quality: Quality) -> Self {
+        let factor = quality.factor();
+        Self {
+            name: format!("{} {}", quality.name(), self.name),
+            effects: self.effects.iter().map(|effect| effect.scaled(factor)).collect(),
+            modifiers: self.modifiers.iter().map(|modifier| modifier.scaled(factor)).collect(),
+            value: (self.value as f64 * factor).round() as u64,
+            ..self.clone()
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-#[derive(ToVariant, FromVariant)]
-#[derive(Debug, Clone)]
-pub struct DropGroup(pub Vec<(Drop, f64)>);
-
-impl DropGroup {
-    fn generate_drop(&self, wave: u64) -> Option<Stack> {
-        use rand::distributions::{Distribution, Uniform};
-        let sum = self.0.iter().fold(0., |accum, (_, chance)| accum + *chance);
-        let mut bucket = Uniform::new(0., sum).sample(&mut rand::thread_rng());
-        for (drop, chance) in &self.0 {
-            if bucket <= *chance {
-                return Some(drop.generate_drop(wave));
-            }
-            bucket -= chance;
+impl Registry {
+    const PATH: &'static str = "res://items/items.json";
+
+    /// Adds better made versions of every item that has effects or modifiers to scale.
+    fn with_qualities(mut self) -> Self {
+        let qualified: Vec<_> = self.items
+            .iter()
+            .filter(|(_, item)| item.has_quality())
+            .flat_map(|(id, item)| vec![
+                (Quality::Fine.tag(id), item.with_quality(Quality::Fine)),
+                (Quality::Masterwork.tag(id), item.with_quality(Quality::Masterwork)),
+            ])
+            .collect();
+        self.items.extend(qualified);
+        self
+    }
+
+    fn load() -> Self {
+        let path = path_ops::abs_asset(Self::PATH.to_owned());
+        log::info!("Loading item registry from {:?}.", path);
+        File::open(&path)
+            .map_err(error::JsonIOError::IO)
+            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            .map(|items| Self { items }.with_qualities())
+            .tap_ok(|registry| log::info!("Loaded {} items into the registry.", registry.items.len()))
+            .tap_err(|e| log::error!("Could not load item registry due to {:?}!", e))
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Item> {
+        self.items.get(id)
+    }
+
+    /// Display name of the item, falling back to the id for unknown items.
+    pub fn name_of<'a>(&'a self, id: &'a str) -> &'a str {
+        self.get(id).map_or(id, |item| item.name.as_str())
+    }
+
+    /// Id of `id` made at `quality`, if the item comes in that quality.
+    pub fn qualified(&self, id: &str, quality: Quality) -> Option<ItemId> {
+        let tagged = quality.tag(id);
+        self.items.get(&tagged).map(|_| tagged)
+    }
+
+    pub fn value_of(&self, id: &str) -> u64 {
+        self.get(id).map_or(0, |item| item.value)
+    }
+
+    pub fn max_in_stack(&self, id: &str) -> u64 {
+        match self.get(id) {
+            Some(item) => item.category.max_in_stack(),
+            None => {
+                log::warn!("Item {:?} is not in the registry.", id);
+                0
+            },
         }
-        None
     }
 }
 
-#[derive(Serialize, Deserialize)]
-#[derive(ToVariant, FromVariant)]
-#[derive(Debug, Clone)]
-pub struct DropTable(pub Vec<(DropGroup, u64)>);
+lazy_static::lazy_static! {
+    static ref REGISTRY: Registry = Registry::load();
+}
 
-impl DropTable {
-    pub fn generate_drops(&self, wave: u64) -> Vec<Stack> {
-        self.0
-            .iter()
-            .flat_map(|(group, count)| (0..*count).filter_map(move |_| group.generate_drop(wave)))
-            .collect()
-    }
+/// The item registry, loaded on first access.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
 }
 
 #[derive(Debug)]
 pub enum Error {
-    Full,
+    Full(Vec<Stack>),
     MismatchedItem(Stack),
     MismatchedItemGroup(Vec<Stack>),
-    TooManyItems(Item, u64),
-    NotEnoughItems(Item, u64),
+    NotEnoughItems(ItemId, u64),
+    Rejected {
+        missing: Vec<(ItemId, u64)>,
+        overflow: Vec<Stack>,
+    },
+}
+
+impl Error {
+    /// The stacks that did not fit, if this error is an overflow.
+    pub fn into_overflow(self) -> Option<Vec<Stack>> {
+        match self {
+            Self::Full(overflow) => Some(overflow),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 #[derive(ToVariant, FromVariant)]
 #[derive(Debug, Clone)]
 pub struct Stack {
-    pub item: Item,
+    pub item: ItemId,
     pub count: u64,
 }
 
 impl Stack {
-    fn is_composed_of(&self, item: &Item) -> bool {
-        &self.item == item
+    fn is_composed_of(&self, item: &str) -> bool {
+        self.item == item
+    }
+
+    /// Whether the stack can stand in for `item`, as any quality of it does.
+    fn counts_as(&self, item: &str) -> bool {
+        self.is_composed_of(item) || Quality::base_id(&self.item) == item
+    }
+
+    fn max_count(&self) -> u64 {
+        registry().max_in_stack(&self.item)
     }
 
-    fn group_stacks(stacks: Vec<Self>) -> HashMap<Item, Vec<Self>> {
-        let mut map = HashMap::new();
+    fn group_stacks(stacks: Vec<Self>) -> HashMap<ItemId, Vec<Self>> {
+        let mut map: HashMap<ItemId, Vec<Self>> = HashMap::new();
         for stack in stacks {
-            if map.contains_key(&stack.item) {
-                map.insert(stack.item.clone(), vec![stack]);
-            } else {
-                if let Some(bucketed_stacks) = map.get_mut(&stack.item) {
-                    bucketed_stacks.push(stack);
-                }
-            }
+            map.entry(stack.item.clone()).or_default().push(stack);
         }
         map
     }
@@ -130,11 +245,11 @@ impl Stack {
         }
 
         { // ensure all stacks have the same item
-            let mut failed_check = true;
-            let item = &stacks[1].item;
+            let mut failed_check = false;
+            let item = &stacks[0].item;
             for stack in &stacks {
                 if !stack.is_composed_of(item) {
-                    failed_check = false;
+                    failed_check = true;
                     break;
                 }
             }
@@ -172,7 +287,7 @@ impl Stack {
             return Ok(Some(from));
         }
 
-        let num_can_merge = into.item.category.max_in_stack() - into.count;
+        let num_can_merge = into.max_count() - into.count;
         if from.count <= num_can_merge {
             into.count += from.count;
             Ok(None)
@@ -184,67 +299,266 @@ impl Stack {
     }
 
     fn is_full(&self) -> bool {
-        self.count >= self.item.category.max_in_stack()
+        self.count >= self.max_count()
     }
 }
 
-#[derive(Default, Debug, Clone)]
+/// Fixed number of slots, each holding at most one stack.
+///
+/// Slots keep their position when other slots change, so the UI can rely on
+/// the ordering.
+#[derive(Debug, Clone)]
 pub struct Inventory {
     max_stacks: usize,
-    pub stacks: HashMap<Item, Stack>,
+    slots: Vec<Option<Stack>>,
+}
+
+impl Inventory {
+    const MAX_STACKS: usize = 24;
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::with_slots(Self::MAX_STACKS)
+    }
 }
 
 impl Inventory {
-    pub fn attempt_add(&mut self, new_stacks: Vec<Stack>) -> Option<Vec<Stack>> {
-        let overflow: Vec<_> = new_stacks
-            .into_iter()
-            .filter_map(|new_stack| {
-                if let Some(stack) = self.stacks.get_mut(&new_stack.item) {
-                    Stack::merge(stack, new_stack).expect("Item to be equivalent.")
-                } else {
-                    self.stacks.insert(new_stack.item.clone(), new_stack);
-                    None
+    pub fn with_slots(max_stacks: usize) -> Self {
+        Self {
+            max_stacks,
+            slots: Vec::with_capacity(max_stacks),
+        }
+    }
+
+    /// Places a single stack, topping up partial stacks first and then
+    /// filling empty slots. Returns whatever did not fit.
+    fn place(&mut self, mut new_stack: Stack) -> Option<Stack> {
+        for slot in self.slots.iter_mut().flatten() {
+            if new_stack.count == 0 {
+                return None;
+            }
+            if slot.is_composed_of(&new_stack.item) {
+                match Stack::merge(slot, new_stack).expect("Item to be equivalent.") {
+                    Some(remaining) => new_stack = remaining,
+                    None => return None,
                 }
+            }
+        }
+
+        let max_count = new_stack.max_count();
+        if max_count == 0 {
+            return Some(new_stack);
+        }
+        let split_off = |new_stack: &mut Stack| {
+            let count = new_stack.count.min(max_count);
+            new_stack.count -= count;
+            Some(Stack {
+                item: new_stack.item.clone(),
+                count,
             })
-            .collect();
-        if overflow.len() != 0 {
-            Some(overflow)
-        } else {
+        };
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if new_stack.count == 0 {
+                return None;
+            }
+            *slot = split_off(&mut new_stack);
+        }
+        while new_stack.count != 0 && self.slots.len() < self.max_stacks {
+            self.slots.push(split_off(&mut new_stack));
+        }
+
+        if new_stack.count == 0 {
             None
+        } else {
+            Some(new_stack)
         }
     }
 
-    pub fn count_items(&self, item: &Item) -> u64 {
-        self.stacks.get(item).map_or(0, |stack| stack.count)
+    /// Adds as much as fits. Anything left over is returned in `Error::Full`.
+    pub fn attempt_add(&mut self, new_stacks: Vec<Stack>) -> Result<(), Error> {
+        let mut overflow = vec![];
+        for (_, group) in Stack::group_stacks(new_stacks) {
+            let group = Stack::collapse_stacks(group)?;
+            overflow.extend(group.into_iter().filter_map(|stack| self.place(stack)));
+        }
+        if overflow.len() != 0 {
+            Err(Error::Full(overflow))
+        } else {
+            Ok(())
+        }
     }
 
-    pub fn attempt_take(&mut self, item: Item, count: u64) -> Result<Stack, Error> {
-        if count > item.category.max_in_stack() {
-            let overflow = count - item.category.max_in_stack();
-            return Err(Error::TooManyItems(item, overflow));
-        }
+    pub fn count_items(&self, item: &str) -> u64 {
+        self.stacks()
+            .filter(|stack| stack.is_composed_of(item))
+            .map(|stack| stack.count)
+            .sum()
+    }
 
-        if count == 0 {
-            return Ok(Stack {
-                item,
-                count: 0,
-            });
-        }
+    /// Count of `item` in every quality it comes in.
+    pub fn count_any_quality(&self, item: &str) -> u64 {
+        self.stacks()
+            .filter(|stack| stack.counts_as(item))
+            .map(|stack| stack.count)
+            .sum()
+    }
 
+    /// Takes `count` of exactly `item`, draining the last slots first. Nothing is
+    /// removed if not enough is held.
+    pub fn attempt_take(&mut self, item: ItemId, count: u64) -> Result<Stack, Error> {
         let num_held = self.count_items(&item);
         if num_held < count {
             return Err(Error::NotEnoughItems(item, count - num_held))
         }
-
-        self.stacks.get_mut(&item).expect("Stack to be present.").count -= count;
-
+        self.drain(&item, count, true);
         Ok(Stack {
             item,
-            count: 0,
+            count,
         })
     }
 
+    /// Takes `count` of `item` in any quality, using up the exact item before any
+    /// better quality of it. Returns the stacks actually taken. Nothing is removed
+    /// if not enough is held.
+    pub fn attempt_take_any_quality(&mut self, item: ItemId, count: u64) -> Result<Vec<Stack>, Error> {
+        let num_held = self.count_any_quality(&item);
+        if num_held < count {
+            return Err(Error::NotEnoughItems(item, count - num_held))
+        }
+        let mut taken = self.drain(&item, count, true);
+        let exact: u64 = taken.iter().map(|stack| stack.count).sum();
+        taken.extend(self.drain(&item, count - exact, false));
+        Ok(taken)
+    }
+
+    /// Removes up to `count` of `item`, or of any quality of it unless `exact`, from
+    /// the last slots first. Returns what was removed, a stack per item id.
+    fn drain(&mut self, item: &str, count: u64, exact: bool) -> Vec<Stack> {
+        let mut taken: Vec<Stack> = vec![];
+        let mut remaining = count;
+        for slot in self.slots.iter_mut().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let matches = |stack: &&mut Stack| if exact {
+                stack.is_composed_of(item)
+            } else {
+                stack.counts_as(item)
+            };
+            if let Some(stack) = slot.as_mut().filter(matches) {
+                let amount = stack.count.min(remaining);
+                stack.count -= amount;
+                remaining -= amount;
+                match taken.iter_mut().find(|t| t.item == stack.item) {
+                    Some(t) => t.count += amount,
+                    None => taken.push(Stack { item: stack.item.clone(), count: amount }),
+                }
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+        taken
+    }
+
     pub fn stacks(&self) -> impl Iterator<Item = &Stack> {
-        self.stacks.iter().map(|(_, s)| s)
+        self.slots.iter().flatten()
+    }
+
+    /// Every slot in order, including the empty ones.
+    pub fn slots(&self) -> impl Iterator<Item = Option<&Stack>> {
+        self.slots
+            .iter()
+            .map(Option::as_ref)
+            .chain(std::iter::repeat(None))
+            .take(self.max_stacks)
+    }
+
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        let staged = self.clone();
+        Transaction {
+            inventory: self,
+            staged,
+            taken: vec![],
+            missing: vec![],
+            overflow: vec![],
+        }
+    }
+}
+
+/// Takes and adds staged against a working copy of an inventory.
+///
+/// The inventory itself is only touched by `commit`, and only if every staged
+/// operation succeeded. Dropping the transaction rolls everything back.
+pub struct Transaction<'a> {
+    inventory: &'a mut Inventory,
+    staged: Inventory,
+    taken: Vec<Stack>,
+    missing: Vec<(ItemId, u64)>,
+    overflow: Vec<Stack>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn take(&mut self, item: ItemId, count: u64) -> &mut Self {
+        match self.staged.attempt_take(item, count) {
+            Ok(stack) => self.taken.push(stack),
+            Err(Error::NotEnoughItems(item, count)) => self.missing.push((item, count)),
+            Err(e) => log::warn!("Unexpected error {:?} while staging a take!", e),
+        }
+        self
+    }
+
+    /// Like `take`, but any quality of `item` will do, as for recipe inputs.
+    pub fn take_any_quality(&mut self, item: ItemId, count: u64) -> &mut Self {
+        match self.staged.attempt_take_any_quality(item, count) {
+            Ok(stacks) => self.taken.extend(stacks),
+            Err(Error::NotEnoughItems(item, count)) => self.missing.push((item, count)),
+            Err(e) => log::warn!("Unexpected error {:?} while staging a take!", e),
+        }
+        self
+    }
+
+    pub fn add(&mut self, stacks: Vec<Stack>) -> &mut Self {
+        match self.staged.attempt_add(stacks) {
+            Ok(()) => (),
+            Err(Error::Full(overflow)) => self.overflow.extend(overflow),
+            Err(e) => log::warn!("Unexpected error {:?} while staging an add!", e),
+        }
+        self
+    }
+
+    /// Count of `item` as it would be after committing.
+    pub fn count_items(&self, item: &str) -> u64 {
+        self.staged.count_items(item)
+    }
+
+    /// Count of `item` in any quality as it would be after committing.
+    pub fn count_any_quality(&self, item: &str) -> u64 {
+        self.staged.count_any_quality(item)
+    }
+
+    /// Hands back the stacks that staged adds could not fit, so they can be
+    /// routed elsewhere instead of rejecting the transaction.
+    pub fn take_overflow(&mut self) -> Vec<Stack> {
+        std::mem::replace(&mut self.overflow, vec![])
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.missing.len() == 0 && self.overflow.len() == 0
+    }
+
+    /// Applies every staged operation, returning the stacks that were taken.
+    pub fn commit(self) -> Result<Vec<Stack>, Error> {
+        let Self { inventory, staged, taken, missing, overflow } = self;
+        if missing.len() == 0 && overflow.len() == 0 {
+            *inventory = staged;
+            Ok(taken)
+        } else {
+            Err(Error::Rejected {
+                missing,
+                overflow,
+            })
+        }
     }
 }
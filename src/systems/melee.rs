@@ -19,12 +19,14 @@ pub struct Cfg {
     frame_nodes_path: NodePath,
     walk_speed: f64,
     initial_attack: u64,
+    pub dmg_scale: f64,
 }
 
 impl Cfg {
     const FRAME_NODE_PATH: &'static str = "Melee";
     const WALK_SPEED: f64 = 0.;
     const INITIAL_ATTACK: u64 = 0;
+    const DMG_SCALE: f64 = 1.;
 }
 
 impl Default for Cfg {
@@ -33,6 +35,7 @@ impl Default for Cfg {
             frame_nodes_path: NodePath::from_str(Self::FRAME_NODE_PATH),
             walk_speed: Self::WALK_SPEED,
             initial_attack: Self::INITIAL_ATTACK,
+            dmg_scale: Self::DMG_SCALE,
         }
     }
 }
@@ -188,11 +191,12 @@ impl System {
                 })?;
             Some((self.cfg.initial_attack, cache.attack(self.cfg.initial_attack, &self.cfg, owner)?))
         })();
+        let dmg_scale = self.cfg.dmg_scale;
         let data = atk_and_id.and_then(|(id, atk)| {
             self.reset(owner);
             atk
                 .map_mut(|atk, base| {
-                    atk.execute(base, dir);
+                    atk.execute(base, dir, dmg_scale);
                     Data {
                         attack: AttackData::from_attack(atk),
                         since_last: Duration::from_millis(0),
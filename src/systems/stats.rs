@@ -0,0 +1,101 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::systems::{equipment::Slot, health::DamageType, items::ItemId};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stat {
+    MaxHp,
+    MaxShield,
+    MoveSpeed,
+    DashSpeed,
+    DashChain,
+    ProjectileDmg,
+    MeleeDmg,
+    Armor,
+    MeleeResist,
+    ProjectileResist,
+    ContactResist,
+    EnvironmentalResist,
+}
+
+impl Stat {
+    pub fn resist(kind: DamageType) -> Self {
+        match kind {
+            DamageType::Melee => Self::MeleeResist,
+            DamageType::Projectile => Self::ProjectileResist,
+            DamageType::Contact => Self::ContactResist,
+            DamageType::Environmental => Self::EnvironmentalResist,
+        }
+    }
+}
+
+/// A change to a single stat. The effective value of a stat is
+/// `(base + sum of add) * product of (1 + mul)`.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Modifier {
+    pub stat: Stat,
+    #[serde(default)]
+    pub add: f64,
+    #[serde(default)]
+    pub mul: f64,
+}
+
+impl Modifier {
+    /// Bonuses scaled by `factor`. Penalties stay as they are.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let scale = |value: f64| if value > 0. { value * factor } else { value };
+        Self {
+            stat: self.stat,
+            add: scale(self.add),
+            mul: scale(self.mul),
+        }
+    }
+}
+
+/// Where a set of modifiers came from, so it can be taken back out later.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Source {
+    Equipment(Slot),
+    Buff(ItemId),
+    Perk(String),
+}
+
+#[derive(Default, Debug)]
+pub struct Stats {
+    base: HashMap<Stat, f64>,
+    sources: HashMap<Source, Vec<Modifier>>,
+}
+
+impl Stats {
+    pub fn set_base(&mut self, stat: Stat, value: f64) {
+        self.base.insert(stat, value);
+    }
+
+    /// Replaces every modifier previously granted by `source`.
+    pub fn add_source(&mut self, source: Source, modifiers: Vec<Modifier>) {
+        self.sources.insert(source, modifiers);
+    }
+
+    /// Adds to the modifiers granted by `source`, keeping the existing ones.
+    pub fn extend_source(&mut self, source: Source, modifier: Modifier) {
+        self.sources.entry(source).or_default().push(modifier);
+    }
+
+    pub fn remove_source(&mut self, source: &Source) -> bool {
+        self.sources.remove(source).is_some()
+    }
+
+    pub fn effective(&self, stat: Stat) -> f64 {
+        let base = self.base.get(&stat).copied().unwrap_or(0.);
+        let (add, mul) = self.sources
+            .values()
+            .flatten()
+            .filter(|modifier| modifier.stat == stat)
+            .fold((0., 1.), |(add, mul), modifier| (add + modifier.add, mul * (1. + modifier.mul)));
+        (base + add) * mul
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Serialize, Deserialize};
+use std::{collections::HashMap, fs::File, time::Duration};
+use tap::{TapOptionOps, TapResultOps};
+
+use crate::{
+    systems::health::{Damage, DamageType, Faction, Source},
+    util::{error, path_ops},
+};
+
+/// How applying a status that is already active plays out.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stacking {
+    /// Restarts the duration.
+    Refresh,
+    /// Adds a stack, up to `max_stacks`, and restarts the duration.
+    Intensify,
+    /// Leaves the active one as it is.
+    Ignore,
+}
+
+impl Default for Stacking {
+    fn default() -> Self {
+        Self::Refresh
+    }
+}
+
+/// Damage dealt every `interval` milliseconds for each stack. Negative amounts heal.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tick {
+    pub amount: f64,
+    #[serde(default = "Tick::default_kind")]
+    pub kind: DamageType,
+    #[serde(default = "Tick::default_interval")]
+    pub interval: u64,
+}
+
+impl Tick {
+    fn default_kind() -> DamageType {
+        DamageType::Environmental
+    }
+
+    fn default_interval() -> u64 {
+        1000
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub name: String,
+    pub millis: u64,
+    #[serde(default)]
+    pub tick: Option<Tick>,
+    /// Multiplies movement speed while active, regardless of stacks.
+    #[serde(default = "Status::default_speed")]
+    pub speed: f64,
+    /// Blocks moving, attacking and shooting while active.
+    #[serde(default)]
+    pub stun: bool,
+    #[serde(default)]
+    pub stacking: Stacking,
+    #[serde(default = "Status::default_max_stacks")]
+    pub max_stacks: u64,
+}
+
+impl Status {
+    fn default_speed() -> f64 {
+        1.
+    }
+
+    fn default_max_stacks() -> u64 {
+        1
+    }
+}
+
+/// Every status effect, keyed by id.
+#[derive(Default, Debug)]
+pub struct Registry {
+    statuses: HashMap<String, Status>,
+}
+
+impl Registry {
+    const PATH: &'static str = "res://status/statuses.json";
+
+    fn load() -> Self {
+        let path = path_ops::abs_asset(Self::PATH.to_owned());
+        log::info!("Loading status effects from {:?}.", path);
+        File::open(&path)
+            .map_err(error::JsonIOError::IO)
+            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
+            .map(|statuses| Self { statuses })
+            .tap_ok(|registry| log::info!("Loaded {} status effects.", registry.statuses.len()))
+            .tap_err(|e| log::error!("Could not load status effects due to {:?}!", e))
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Status> {
+        self.statuses.get(id)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Registry = Registry::load();
+}
+
+/// The status registry, loaded on first access.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+#[derive(Debug)]
+struct Active {
+    id: String,
+    status: Status,
+    stacks: u64,
+    remaining: Duration,
+    until_tick: Duration,
+    /// Who applied it, so ticks are credited to them.
+    source: Option<Source>,
+}
+
+impl Active {
+    fn tick_source(&self) -> Source {
+        match &self.source {
+            Some(source) => Source { attack: self.id.clone(), ..source.clone() },
+            None => Source {
+                attacker_id: 0,
+                attacker: self.status.name.clone(),
+                attack: self.id.clone(),
+                faction: Faction::Neutral,
+            },
+        }
+    }
+}
+
+/// Status effects hosted by anything with health.
+#[derive(Default, Debug)]
+pub struct Statuses {
+    active: Vec<Active>,
+    changed: bool,
+}
+
+impl Statuses {
+    /// Applies the status registered as `id`, returning whether anything changed.
+    pub fn apply(&mut self, id: &str, source: Option<Source>) -> bool {
+        let status = match registry().get(id).tap_none(|| log::warn!("Unknown status effect {:?}.", id)) {
+            Some(status) => status,
+            None => return false,
+        };
+        let duration = Duration::from_millis(status.millis);
+        if let Some(active) = self.active.iter_mut().find(|active| active.id == id) {
+            match status.stacking {
+                Stacking::Ignore => return false,
+                Stacking::Refresh => (),
+                Stacking::Intensify => active.stacks = (active.stacks + 1).min(status.max_stacks.max(1)),
+            }
+            active.remaining = duration;
+            active.source = source;
+        } else {
+            let until_tick = status.tick.map_or(duration, |tick| Duration::from_millis(tick.interval));
+            self.active.push(Active {
+                id: id.to_owned(),
+                status: status.clone(),
+                stacks: 1,
+                remaining: duration,
+                until_tick,
+                source,
+            });
+        }
+        self.changed = true;
+        true
+    }
+
+    /// Counts down every status, dropping expired ones. Returns the ticks that came due,
+    /// as damage with a negative amount for healing.
+    pub fn process(&mut self, delta: Duration) -> Vec<Damage> {
+        let mut ticks = vec![];
+        for active in self.active.iter_mut() {
+            if let Some(tick) = active.status.tick {
+                let interval = Duration::from_millis(tick.interval.max(1));
+                let mut elapsed = delta.min(active.remaining);
+                while elapsed >= active.until_tick {
+                    elapsed -= active.until_tick;
+                    active.until_tick = interval;
+                    ticks.push(Damage::new(tick.amount * active.stacks as f64, tick.kind, active.tick_source()));
+                }
+                active.until_tick -= elapsed;
+            }
+        }
+        let before = self.active.len();
+        self.active.retain(|active| active.remaining > delta);
+        for active in self.active.iter_mut() {
+            active.remaining -= delta;
+        }
+        if self.active.len() != before {
+            self.changed = true;
+        }
+        ticks
+    }
+
+    pub fn clear(&mut self) {
+        if self.active.len() != 0 {
+            self.changed = true;
+        }
+        self.active.clear();
+    }
+
+    pub fn speed_factor(&self) -> f64 {
+        self.active.iter().map(|active| active.status.speed.max(0.)).product()
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.active.iter().any(|active| active.status.stun)
+    }
+
+    /// Whether the active statuses changed since last asked.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    /// A line per active status, for display.
+    pub fn summary(&self) -> Vec<String> {
+        self.active
+            .iter()
+            .map(|active| if active.stacks > 1 {
+                format!("{} x{}", active.status.name, active.stacks)
+            } else {
+                active.status.name.clone()
+            })
+            .collect()
+    }
+}
@@ -10,6 +10,8 @@ use gdnative::{
 
 use tap::TapResultOps;
 
+use crate::crafting::Station;
+
 mod hud;
 pub use hud::HUD;
 mod end;
@@ -122,7 +124,7 @@ impl UI {
         }
     }
 
-    pub fn to_forge(&self, owner: Control) {
+    pub fn to_forge(&self, owner: Control, station: Station) {
         unsafe {
             if let Some(mut tree) = owner.to_node().get_tree() {
                 if let Some(forge_ui) = owner.get_node(self.cfg.crafting.new_ref()) {
@@ -130,6 +132,7 @@ impl UI {
                         if let Some(ui) = ui.cast() {
                             if let Some(instance) = Instance::<crate::ui::Crafting>::try_from_base(ui) {
                                 instance.map_mut(|ui, base| {
+                                    ui.set_station(station);
                                     ui.render_recipes(base);
                                 })
                                 .tap_err(|e| log::error!("Could not invoke `render_recipes` due to {:?}.", e));
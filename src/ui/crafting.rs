@@ -16,98 +16,44 @@ use gdnative::{
 };
 
 use tap::TapResultOps;
-use std::fs::File;
+use std::{collections::{HashMap, HashSet}, path::Path};
 
-use crate::{crafting::{RecipeVariant, Recipes}, systems::items::{Category, Inventory, Item, Stack}, util::{error, path_ops}};
-
-lazy_static::lazy_static! {
-    static ref base_pixel: Item = Item {
-        category: Category::Raw,
-        name: "base pixel".to_owned(),
-        desc: "the source of all things".to_owned(),
-        can_use: false,
-    };
-    static ref moderate_pixel: Item = Item {
-        category: Category::Raw,
-        name: "moderate pixel".to_owned(),
-        desc: "7".to_owned(),
-        can_use: false,
-    };
-    static ref advanced_pixel: Item = Item {
-        category: Category::Raw,
-        name: "advanced pixel".to_owned(),
-        desc: "21".to_owned(),
-        can_use: false,
-    };
-    static ref master_pixel: Item = Item {
-        category: Category::Raw,
-        name: "master pixel".to_owned(),
-        desc: "42".to_owned(),
-        can_use: false,
-    };
-    static ref generic_pixel: Item = Item {
-        category: Category::Raw,
-        name: "generic pixel".to_owned(),
-        desc: "42n".to_owned(),
-        can_use: false,
-    };
-    static ref cheap_health_potion: Item = Item {
-        category: Category::Raw,
-        name: "cheap health potion".to_owned(),
-        desc: "restore up to 10 health".to_owned(),
-        can_use: true,
-    };
-    static ref health_elixir: Item = Item {
-        category: Category::Raw,
-        name: "health elixir".to_owned(),
-        desc: "increase max health by 10".to_owned(),
-        can_use: true,
-    };
-    static ref recipes: Vec<Recipes> = vec![
-        // pixel enhancement
-        Recipes {
-            input: maplit::hashmap! { (*base_pixel).clone() => 70u64 },
-            output: vec![Stack { item: (*moderate_pixel).clone(), count: 1, }],
-        },
-        Recipes {
-            input: maplit::hashmap! { (*moderate_pixel).clone() => 30u64 },
-            output: vec![Stack { item: (*advanced_pixel).clone(), count: 1, }],
-        },
-        Recipes {
-            input: maplit::hashmap! { (*advanced_pixel).clone() => 20u64 },
-            output: vec![Stack { item: (*master_pixel).clone(), count: 1, }],
-        },
-        Recipes {
-            input: maplit::hashmap! { (*master_pixel).clone() => 100u64 },
-            output: vec![Stack { item: (*generic_pixel).clone(), count: 1, }],
-        },
-        // potions
-        Recipes {
-            input: maplit::hashmap! { (*base_pixel).clone() => 100u64 },
-            output: vec![Stack { item: (*cheap_health_potion).clone(), count: 1, }],
-        },
-        Recipes {
-            input: maplit::hashmap! { (*master_pixel).clone() => 100u64 },
-            output: vec![Stack { item: (*health_elixir).clone(), count: 1, }],
-        },
-    ];
-}
+use crate::{
+    crafting::{Batch, Condition, Limit, Plan, Recipes, Station},
+    entity::Player,
+    profile::Profile,
+    systems::items::{self, Stack},
+    util::path_ops,
+};
 
 pub struct Crafting {
     marking_col: NodePath,
     in_col: NodePath,
     out_col: NodePath,
+    stash_col: NodePath,
+    report: NodePath,
     player: NodePath,
     recipe_dir: String,
     listing: Vec<Recipes>,
+    /// Only recipes made at this station are shown.
+    station: Station,
+    /// Recipes discovered during this run.
+    known: HashSet<String>,
+    /// Recipes the player chose to spend catalysts on.
+    use_catalysts: HashSet<String>,
+    wave: u64,
 }
 
 impl Crafting {
     const MARKING_PATH: &'static str = "";
     const IN_PATH: &'static str = "";
     const OUT_PATH: &'static str = "";
+    const STASH_PATH: &'static str = "";
+    const REPORT_PATH: &'static str = "";
     const PLAYER_PATH: &'static str = "";
-    const RECIPE_DIR: &'static str = "";
+    const RECIPE_DIR: &'static str = "res://recipes";
+    /// Batch buttons next to each recipe, a count of 0 crafts as many as possible.
+    const BATCHES: &'static [(&'static str, u64)] = &[("x10", 10), ("Max", 0)];
 }
 
 impl Default for Crafting {
@@ -116,9 +62,15 @@ impl Default for Crafting {
             marking_col: Self::MARKING_PATH.into(),
             in_col: Self::IN_PATH.into(),
             out_col: Self::OUT_PATH.into(),
+            stash_col: Self::STASH_PATH.into(),
+            report: Self::REPORT_PATH.into(),
             player: Self::PLAYER_PATH.into(),
             recipe_dir: Self::RECIPE_DIR.to_owned(),
             listing: Default::default(),
+            station: Default::default(),
+            known: Default::default(),
+            use_catalysts: Default::default(),
+            wave: 0,
         }
     }
 }
@@ -168,6 +120,22 @@ impl NativeClass for Crafting {
             setter: |this: &mut Self, path| this.out_col = path,
             usage: PropertyUsage::DEFAULT,
         });
+        builder.add_property(Property {
+            name: "stash_path",
+            default: NodePath::from_str(Self::STASH_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.stash_col.new_ref(),
+            setter: |this: &mut Self, path| this.stash_col = path,
+            usage: PropertyUsage::DEFAULT,
+        });
+        builder.add_property(Property {
+            name: "report_path",
+            default: NodePath::from_str(Self::REPORT_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.report.new_ref(),
+            setter: |this: &mut Self, path| this.report = path,
+            usage: PropertyUsage::DEFAULT,
+        });
         builder.add_property(Property {
             name: "player_path",
             default: NodePath::from_str(Self::PLAYER_PATH),
@@ -184,12 +152,149 @@ impl Crafting {
     #[export]
     fn _ready(&mut self, _owner: CenterContainer) {
         self.recipe_dir = path_ops::abs_asset(self.recipe_dir.clone());
-        log::info!("Attempting to laod file from {:?}.", self.recipe_dir);
-        self.listing = File::open(&self.recipe_dir)
-            .map_err(error::JsonIOError::IO)
-            .and_then(|f| json::from_reader(f).map_err(error::JsonIOError::Json))
-            .tap_err(|e| log::error!("Could not find recipe due to {:?}!", e))
-            .unwrap_or_else(|_| recipes.clone());
+        log::info!("Loading recipes from {:?}.", self.recipe_dir);
+        let (listing, errors) = Recipes::load_dir(Path::new(&self.recipe_dir));
+        for e in errors {
+            log::error!("Rejected recipe data: {:?}.", e);
+        }
+        self.listing = listing;
+    }
+
+    #[export]
+    fn set_wave_num(&mut self, _owner: CenterContainer, wave_num: u64) {
+        self.wave = wave_num;
+    }
+
+    /// Buys a recipe with banked currency, if it can be bought at all.
+    #[export]
+    fn buy_recipe(&mut self, owner: CenterContainer, id: String) {
+        let cost = self.listing
+            .iter()
+            .find(|recipe| recipe.id == id)
+            .and_then(|recipe| recipe.discovery.iter().find_map(|condition| match condition {
+                Condition::Purchase { cost } => Some(*cost),
+                _ => None,
+            }));
+        let cost = if let Some(cost) = cost {
+            cost
+        } else {
+            log::warn!("Recipe {:?} cannot be bought.", id);
+            return;
+        };
+        match Profile::get_autoload(unsafe { owner.to_node() }) {
+            Some(profile) => match profile.map_mut(|profile, _| profile.buy_recipe(&id, cost)) {
+                Ok(Ok(())) => log::info!("Bought recipe {:?} for {}.", id, cost),
+                e => log::warn!("Could not buy recipe {:?} due to {:?}.", id, e),
+            },
+            None => log::warn!("No profile to buy recipe {:?} with.", id),
+        }
+        self.render_recipes(owner);
+    }
+
+    /// Switches whether crafting the recipe spends its catalysts.
+    #[export]
+    fn toggle_catalysts(&mut self, owner: CenterContainer, id: String) {
+        if !self.use_catalysts.remove(&id) {
+            self.use_catalysts.insert(id);
+        }
+        self.render_recipes(owner);
+    }
+
+    #[export]
+    fn craft_recipe(&mut self, owner: CenterContainer, id: String) {
+        self.craft_batch(owner, id, 1);
+    }
+
+    /// Crafts `count` of the recipe at once, or as many as possible if `count` is 0.
+    #[export]
+    fn craft_batch(&mut self, owner: CenterContainer, id: String, count: u64) {
+        // Only what the player can see here can be crafted: right station, discovered and unlocked.
+        let (listing, _) = self.visible_recipes(&owner);
+        let recipe = if let Some(recipe) = listing.into_iter().find(|recipe| recipe.id == id) {
+            recipe
+        } else {
+            log::warn!("No recipe {:?} to craft.", id);
+            return;
+        };
+        let count = if count == 0 { None } else { Some(count) };
+        let use_catalysts = self.use_catalysts.contains(&id);
+        let player = unsafe { owner.get_node(self.player.new_ref()).and_then(|n| n.cast()) }
+            .and_then(Instance::<Player>::try_from_base);
+        let report = match player {
+            Some(player) => player
+                .map_mut(|player, _| {
+                    let batch = recipe.craft_batch(count, use_catalysts, &mut player.inventory, &mut player.stash);
+                    player.record_held();
+                    batch
+                })
+                .map_err(|e| log::error!("Could not find player due to {:?}!", e))
+                .ok(),
+            None => None,
+        };
+        let text = match report {
+            Some(Ok(batch)) => {
+                log::info!("Crafted {:?} {} times, limited by {:?}.", recipe.id, batch.crafted, batch.limit);
+                Self::batch_summary(&batch)
+            },
+            Some(Err(e)) => {
+                log::error!("Failed to craft a batch of {:?} due to {:?}.", recipe, e);
+                "Could not craft.".to_owned()
+            },
+            None => return,
+        };
+        if let Some(mut label) = unsafe { owner.get_node(self.report.new_ref()).and_then(|n| n.cast::<Label>()) } {
+            unsafe { label.set_text(text.into()) };
+        }
+        self.render_recipes(owner);
+    }
+
+    /// Crafts whatever the recipe is short on and then the recipe itself, as one transaction.
+    #[export]
+    fn craft_chain(&mut self, owner: CenterContainer, id: String) {
+        let (listing, _) = self.visible_recipes(&owner);
+        let recipe = if let Some(recipe) = listing.iter().find(|recipe| recipe.id == id) {
+            recipe
+        } else {
+            log::warn!("No recipe {:?} to craft a chain for.", id);
+            return;
+        };
+        let player = unsafe { owner.get_node(self.player.new_ref()).and_then(|n| n.cast()) }
+            .and_then(Instance::<Player>::try_from_base);
+        if let Some(player) = player {
+            player
+                .map_mut(|player, _| {
+                    let plan = Plan::for_recipe(&listing, recipe, Plan::held(&player.inventory, &player.stash));
+                    if !plan.is_complete() {
+                        log::warn!("Cannot craft a chain for {:?}, short on {:?}.", id, plan.shortfall);
+                        return;
+                    }
+                    match plan.execute(&mut player.inventory, &mut player.stash) {
+                        Ok(()) => {
+                            log::info!("Successfully crafted chain {:?}!", plan.steps);
+                            player.record_held();
+                        },
+                        Err(e) => log::error!("Failed to craft chain {:?} due to {:?}.", plan.steps, e),
+                    }
+                })
+                .map_err(|e| log::error!("Could not find player due to {:?}!", e))
+                .ok();
+        }
+        self.render_recipes(owner);
+    }
+
+    fn batch_summary(batch: &Batch) -> String {
+        let why = match &batch.limit {
+            Limit::Requested => "".to_owned(),
+            Limit::Input(item) => format!(", out of {}", items::registry().name_of(item)),
+            Limit::Space => ", out of space".to_owned(),
+            Limit::BatchSize => ", batch limit reached".to_owned(),
+        };
+        let made = batch.made
+            .iter()
+            .map(|stack| format!("{} {}", stack.count, items::registry().name_of(&stack.item)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("Made {}{}.", made, why)
     }
 
     fn recipe_columns(&self, owner: &CenterContainer) -> Option<(VBoxContainer, VBoxContainer, VBoxContainer)> {
@@ -202,33 +307,83 @@ impl Crafting {
         }
     }
 
-    fn create_entry(recipe: &Recipes, inv: &Inventory) -> (Button, Control, Control) {
+    fn plan_summary(plan: &Plan) -> String {
+        let list = |counts: &HashMap<items::ItemId, u64>| counts
+            .iter()
+            .map(|(item, count)| format!("{} {}", count, items::registry().name_of(item)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if plan.is_complete() {
+            format!("Uses {}.", list(&plan.materials))
+        } else {
+            format!("Short on {}.", list(&plan.shortfall))
+        }
+    }
+
+    /// Spells out everything that is left to chance.
+    fn odds_summary(recipe: &Recipes) -> String {
+        let registry = items::registry();
+        let list = |stacks: &[Stack]| stacks
+            .iter()
+            .map(|stack| format!("{} {}", stack.count, registry.name_of(&stack.item)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let percent = |chance: f64| (chance * 100.).round();
+        let mut lines = vec![];
+        if !recipe.yields.is_exact() {
+            lines.push(format!("Yields {}% of the listed outputs, give or take {}%.", percent(recipe.yields.rate), percent(recipe.yields.spread)));
+        }
+        let total: f64 = recipe.outcomes.iter().map(|outcome| outcome.weight).sum();
+        for outcome in recipe.outcomes.iter().filter(|_| total > 0.) {
+            let output = if outcome.output.len() == 0 { "nothing".to_owned() } else { list(&outcome.output) };
+            lines.push(format!("{}% chance of {} more.", percent(outcome.weight / total), output));
+        }
+        if let Some(bonus) = recipe.bonus.as_ref() {
+            lines.push(format!("{}% chance of a bonus {}.", percent(bonus.chance), list(&bonus.output)));
+        }
+        if recipe.quality.fine > 0. || recipe.quality.masterwork > 0. {
+            lines.push(format!(
+                "{}% fine, {}% masterwork.",
+                percent(recipe.quality.fine),
+                percent(recipe.quality.masterwork),
+            ));
+        }
+        for catalyst in recipe.catalysts.iter() {
+            lines.push(format!(
+                "Can use {} {} for +{}% fine, +{}% masterwork, +{}% bonus.",
+                catalyst.count,
+                registry.name_of(&catalyst.item),
+                percent(catalyst.fine),
+                percent(catalyst.masterwork),
+                percent(catalyst.bonus),
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// `plan` is the chain that would make the recipe's output when it cannot be crafted directly.
+    fn create_entry(recipe: &Recipes, craftable: bool, plan: Option<&Plan>) -> (Button, Control, Control) {
         let mut mark = Button::new();
         let mut ins = HBoxContainer::new();
         let mut outs = HBoxContainer::new();
         unsafe {
-            let (avail, mark_text) = if recipe.input.iter().any(|(item, count)| inv.count_items(item) >= *count) {
-                (true, "Available")
-            } else {
-                (false, "Cannot make")
+            let (avail, mark_text) = match plan {
+                _ if craftable => (true, "Available".to_owned()),
+                Some(plan) if plan.is_complete() => (true, format!("Chain ({} crafts)", plan.craft_count())),
+                _ => (false, "Cannot make".to_owned()),
             };
+            if let Some(plan) = plan {
+                mark.set_tooltip(Self::plan_summary(plan).into());
+            } else if !recipe.is_deterministic() {
+                mark.set_tooltip(Self::odds_summary(recipe).into());
+            }
             mark.set_disabled(!avail);
             mark.set_text(mark_text.into());
-            for input in recipe.input.iter() {
-                let mut name = Label::new();
-                let mut num = Label::new();
-                name.set_text(input.0.name.clone().into());
-                num.set_text(input.1.to_string().into());
-                ins.add_child(Some(name.to_node()), false);
-                ins.add_child(Some(num.to_node()), false);
+            for (item, count) in recipe.input.iter() {
+                Self::add_stack_labels(&mut ins, item, &count.to_string());
             }
             for output in recipe.output.iter() {
-                let mut name = Label::new();
-                let mut num = Label::new();
-                name.set_text(output.item.name.clone().into());
-                num.set_text(output.count.to_string().into());
-                outs.add_child(Some(name.to_node()), false);
-                outs.add_child(Some(num.to_node()), false);
+                Self::add_stack_labels(&mut outs, &output.item, &Self::output_count(recipe, output.count));
             }
             mark.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
             ins.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
@@ -241,13 +396,146 @@ impl Crafting {
         }
     }
 
+    /// Outputs with an uncertain yield show what they come to on average.
+    fn output_count(recipe: &Recipes, count: u64) -> String {
+        if recipe.yields.is_exact() {
+            count.to_string()
+        } else {
+            format!("~{}", recipe.yields.expected(count))
+        }
+    }
+
+    fn add_stack_labels(row: &mut HBoxContainer, item: &str, count: &str) {
+        let mut name = Label::new();
+        let mut num = Label::new();
+        unsafe {
+            name.set_text(items::registry().name_of(item).into());
+            num.set_text(count.into());
+            row.add_child(Some(name.to_node()), false);
+            row.add_child(Some(num.to_node()), false);
+        }
+    }
+
+    fn condition_hint(condition: &Condition) -> String {
+        let registry = items::registry();
+        match condition {
+            Condition::Holding { item } => format!("hold {}", registry.name_of(item)),
+            Condition::Wave { wave } => format!("reach wave {}", wave),
+            Condition::Blueprint { item } => format!("find {}", registry.name_of(item)),
+            Condition::Purchase { cost } => format!("buy for {}", cost),
+        }
+    }
+
+    /// A recipe still to be discovered shows its outputs and how to discover it, but not its inputs.
+    fn create_hint(recipe: &Recipes) -> (Button, Control, Control) {
+        let mut mark = Button::new();
+        let mut ins = HBoxContainer::new();
+        let mut outs = HBoxContainer::new();
+        unsafe {
+            let cost = recipe.discovery.iter().find_map(|condition| match condition {
+                Condition::Purchase { cost } => Some(*cost),
+                _ => None,
+            });
+            match cost {
+                Some(cost) => mark.set_text(format!("Buy ({})", cost).into()),
+                None => mark.set_text("Locked".into()),
+            }
+            mark.set_disabled(cost.is_none());
+            let mut hint = Label::new();
+            let conditions: Vec<_> = recipe.discovery.iter().map(Self::condition_hint).collect();
+            hint.set_text(format!("To discover: {}", conditions.join(" or ")).into());
+            ins.add_child(Some(hint.to_node()), false);
+            for output in recipe.output.iter() {
+                Self::add_stack_labels(&mut outs, &output.item, &Self::output_count(recipe, output.count));
+            }
+            mark.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
+            ins.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
+            outs.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
+            (
+                mark,
+                ins.to_control(),
+                outs.to_control(),
+            )
+        }
+    }
+
+    fn create_stash_entry(stack: &Stack) -> (Button, HBoxContainer) {
+        let mut entry = HBoxContainer::new();
+        let mut name = Label::new();
+        let mut num = Label::new();
+        let mut retrieve = Button::new();
+        unsafe {
+            name.set_text(items::registry().name_of(&stack.item).into());
+            num.set_text(stack.count.to_string().into());
+            retrieve.set_text("Retrieve".into());
+            entry.add_child(Some(name.to_node()), false);
+            entry.add_child(Some(num.to_node()), false);
+            entry.add_child(Some(retrieve.to_node()), false);
+        }
+        (retrieve, entry)
+    }
+
+    /// Marks every recipe whose discovery conditions are now met as known. Blueprints are
+    /// also remembered in the profile.
+    fn discover(&mut self, owner: &CenterContainer, player: &Player) {
+        let profile = Profile::get_autoload(unsafe { owner.to_node() });
+        for recipe in self.listing.iter() {
+            for condition in recipe.discovery.iter() {
+                match condition {
+                    Condition::Holding { item } if player.has_held(item) => {
+                        self.known.insert(recipe.id.clone());
+                    },
+                    Condition::Wave { wave } if self.wave >= *wave => {
+                        self.known.insert(recipe.id.clone());
+                    },
+                    Condition::Blueprint { item } if player.has_held(item) => {
+                        self.known.insert(recipe.id.clone());
+                        if let Some(profile) = profile.as_ref() {
+                            profile
+                                .map_mut(|profile, _| profile.learn_recipe(&recipe.id))
+                                .tap_err(|e| log::error!("Could not record blueprint due to {:?}.", e))
+                                .ok();
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    pub fn set_station(&mut self, station: Station) {
+        self.station = station;
+    }
+
+    /// Known recipes at the current station, then the ones still to be discovered.
+    /// Recipes whose outputs are gated behind a profile unlock are left out entirely.
+    fn visible_recipes(&self, owner: &CenterContainer) -> (Vec<Recipes>, Vec<Recipes>) {
+        let split = |profile: Option<&Profile>| self.listing
+            .iter()
+            .filter(|recipe| recipe.station == self.station)
+            .filter(|recipe| profile.map_or(true, |profile| {
+                recipe.output.iter().all(|stack| profile.is_item_unlocked(&stack.item))
+            }))
+            .cloned()
+            .partition(|recipe| {
+                recipe.discovery.len() == 0
+                    || self.known.contains(&recipe.id)
+                    || profile.map_or(false, |profile| profile.knows_recipe(&recipe.id))
+            });
+        Profile::get_autoload(unsafe { owner.to_node() })
+            .and_then(|profile| profile.map_mut(|profile, _| split(Some(profile))).ok())
+            .unwrap_or_else(|| split(None))
+    }
+
     #[export]
-    pub fn render_recipes(&self, owner: CenterContainer) {
+    pub fn render_recipes(&mut self, owner: CenterContainer) {
         log::info!("Rendering recipes: {:?}", self.listing);
         unsafe {
             if let Some(node) = owner.get_node(self.player.new_ref()).and_then(|n| n.cast()) {
-                if let Some(player) = Instance::<crate::entity::Player>::try_from_base(node) {
+                if let Some(player) = Instance::<Player>::try_from_base(node) {
                     player.map_mut(move |player, base| {
+                        self.discover(&owner, player);
+                        let (listing, locked) = self.visible_recipes(&owner);
                         if let Some(mut recipe_columns) = self.recipe_columns(&owner) {
                             while let Some(child) = recipe_columns.0.get_child(0) {
                                 recipe_columns.0.remove_child(Some(child));
@@ -258,18 +546,82 @@ impl Crafting {
                             while let Some(child) = recipe_columns.2.get_child(0) {
                                 recipe_columns.2.remove_child(Some(child));
                             }
-                            self.listing
-                                .iter()
-                                .map(|recipe| (recipe, Self::create_entry(recipe, &player.inventory)))
-                                .for_each(|(recipe, (mut mark, ins, outs))| {
+                            let held = Plan::held(&player.inventory, &player.stash);
+                            for recipe in listing.iter() {
+                                let craftable = recipe.input
+                                    .iter()
+                                    .all(|(item, count)| held.get(item).copied().unwrap_or(0) >= *count);
+                                let plan = if craftable {
+                                    None
+                                } else {
+                                    Some(Plan::for_recipe(&listing, recipe, held.clone()))
+                                };
+                                let (mut mark, ins, outs) = Self::create_entry(recipe, craftable, plan.as_ref());
+                                let mut arr = VariantArray::new();
+                                arr.push(&recipe.id.to_variant());
+                                let method = match plan {
+                                    Some(plan) if plan.is_complete() => "craft_chain",
+                                    _ => "craft_recipe",
+                                };
+                                mark.connect("button_up".into(), Some(owner.to_object()), method.into(), arr, 0)
+                                    .tap_err(|e| log::error!("Could not connect crafting signal due to {:?}.", e));
+                                let mut row = HBoxContainer::new();
+                                row.set_custom_minimum_size(gdnative::Vector2::new(0., 50.));
+                                row.add_child(Some(mark.to_node()), false);
+                                for &(text, count) in Self::BATCHES {
+                                    let mut batch = Button::new();
+                                    batch.set_text(text.into());
+                                    batch.set_disabled(!craftable);
+                                    let mut arr = VariantArray::new();
+                                    arr.push(&recipe.id.to_variant());
+                                    arr.push(&count.to_variant());
+                                    batch.connect("button_up".into(), Some(owner.to_object()), "craft_batch".into(), arr, 0)
+                                        .tap_err(|e| log::error!("Could not connect batch crafting signal due to {:?}.", e));
+                                    row.add_child(Some(batch.to_node()), false);
+                                }
+                                if recipe.catalysts.len() != 0 {
+                                    let mut toggle = Button::new();
+                                    let text = if self.use_catalysts.contains(&recipe.id) {
+                                        "Catalysts: on"
+                                    } else {
+                                        "Catalysts: off"
+                                    };
+                                    toggle.set_text(text.into());
                                     let mut arr = VariantArray::new();
-                                    arr.push(&RecipeVariant::from(recipe.clone()).to_variant());
-                                    mark.connect("button_up".into(), Some(base.to_object()), "craft_recipe".into(), arr, 0);
-                                    mark.connect("button_up".into(), Some(owner.to_object()), "render_recipes".into(), VariantArray::new(), 0);
-                                    recipe_columns.0.add_child(Some(mark.to_node()), false);
-                                    recipe_columns.1.add_child(Some(ins.to_node()), false);
-                                    recipe_columns.2.add_child(Some(outs.to_node()), false);
-                                });
+                                    arr.push(&recipe.id.to_variant());
+                                    toggle.connect("button_up".into(), Some(owner.to_object()), "toggle_catalysts".into(), arr, 0)
+                                        .tap_err(|e| log::error!("Could not connect catalyst toggle signal due to {:?}.", e));
+                                    row.add_child(Some(toggle.to_node()), false);
+                                }
+                                recipe_columns.0.add_child(Some(row.to_node()), false);
+                                recipe_columns.1.add_child(Some(ins.to_node()), false);
+                                recipe_columns.2.add_child(Some(outs.to_node()), false);
+                            }
+                            for recipe in locked.iter() {
+                                let (mut mark, ins, outs) = Self::create_hint(recipe);
+                                let mut arr = VariantArray::new();
+                                arr.push(&recipe.id.to_variant());
+                                mark.connect("button_up".into(), Some(owner.to_object()), "buy_recipe".into(), arr, 0)
+                                    .tap_err(|e| log::error!("Could not connect recipe purchase signal due to {:?}.", e));
+                                recipe_columns.0.add_child(Some(mark.to_node()), false);
+                                recipe_columns.1.add_child(Some(ins.to_node()), false);
+                                recipe_columns.2.add_child(Some(outs.to_node()), false);
+                            }
+                        }
+                        if let Some(mut stash_col) = owner.get_node(self.stash_col.new_ref()) {
+                            while let Some(child) = stash_col.get_child(0) {
+                                stash_col.remove_child(Some(child));
+                            }
+                            for stack in player.stash.stacks() {
+                                let (mut retrieve, entry) = Self::create_stash_entry(stack);
+                                let mut arr = VariantArray::new();
+                                arr.push(&stack.item.to_variant());
+                                retrieve.connect("button_up".into(), Some(base.to_object()), "retrieve_from_stash".into(), arr, 0)
+                                    .tap_err(|e| log::error!("Could not connect stash retrieval signal due to {:?}.", e));
+                                retrieve.connect("button_up".into(), Some(owner.to_object()), "render_recipes".into(), VariantArray::new(), 0)
+                                    .tap_err(|e| log::error!("Could not connect stash retrieval signal due to {:?}.", e));
+                                stash_col.add_child(Some(entry.to_node()), false);
+                            }
                         }
                     }).tap_err(|e| log::error!("Could not find player due to {:?}!", e));
                 }
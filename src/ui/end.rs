@@ -1,25 +1,34 @@
 use gdnative::{
+    Button,
     Control,
     Container,
     GodotString,
+    HBoxContainer,
     init::{ClassBuilder, Property, PropertyHint, PropertyUsage},
     Label,
     NativeClass,
     NodePath,
+    ToVariant,
     user_data::MutexData,
+    VariantArray,
 };
+use tap::TapResultOps;
 
-use crate::records::{Record, Records};
+use crate::{records::{Record, Records}, profile::{Profile, Unlock}};
 
 pub struct End {
     wave: NodePath,
     scoreboard: NodePath,
+    bank: NodePath,
+    shop: NodePath,
     game_scene: GodotString,
 }
 
 impl End {
     const WAVE_PATH: &'static str = "";
     const SCOREBOARD_PATH: &'static str = "";
+    const BANK_PATH: &'static str = "";
+    const SHOP_PATH: &'static str = "";
     const GAME_SCENE: &'static str = "";
 }
 
@@ -28,6 +37,8 @@ impl Default for End {
         Self {
             wave: Self::WAVE_PATH.into(),
             scoreboard: Self::SCOREBOARD_PATH.into(),
+            bank: Self::BANK_PATH.into(),
+            shop: Self::SHOP_PATH.into(),
             game_scene: Self::GAME_SCENE.into(),
         }
     }
@@ -62,6 +73,22 @@ impl NativeClass for End {
             setter: |this: &mut Self, path| this.scoreboard = path,
             usage: PropertyUsage::DEFAULT,
         });
+        builder.add_property(Property {
+            name: "bank_path",
+            default: NodePath::from_str(Self::BANK_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.bank.new_ref(),
+            setter: |this: &mut Self, path| this.bank = path,
+            usage: PropertyUsage::DEFAULT,
+        });
+        builder.add_property(Property {
+            name: "shop_path",
+            default: NodePath::from_str(Self::SHOP_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.shop.new_ref(),
+            setter: |this: &mut Self, path| this.shop = path,
+            usage: PropertyUsage::DEFAULT,
+        });
         builder.add_property(Property {
             name: "game_scene",
             default: Self::GAME_SCENE.into(),
@@ -98,6 +125,28 @@ impl End {
             }
         }
     }
+
+    fn create_unlock_entry(unlock: &Unlock, owned: bool, affordable: bool) -> (Button, HBoxContainer) {
+        let mut entry = HBoxContainer::new();
+        let mut name = Label::new();
+        let mut desc = Label::new();
+        let mut buy = Button::new();
+        unsafe {
+            name.set_text(unlock.name.as_str().into());
+            desc.set_text(unlock.desc.as_str().into());
+            desc.set_h_size_flags(Control::SIZE_EXPAND_FILL);
+            if owned {
+                buy.set_text("Owned".into());
+            } else {
+                buy.set_text(format!("Buy ({})", unlock.cost).into());
+            }
+            buy.set_disabled(owned || !affordable);
+            entry.add_child(Some(name.to_node()), false);
+            entry.add_child(Some(desc.to_node()), false);
+            entry.add_child(Some(buy.to_node()), false);
+        }
+        (buy, entry)
+    }
 }
 
 #[methods]
@@ -135,11 +184,59 @@ impl End {
         if let (Some(r), Some(mut wave)) = (most_recent, wave) {
             log::info!("Setting wave text.");
             unsafe {
-                wave.set_text(format!("You survived until wave {}! Congratulations!", r.wave_num).into());
+                wave.set_text(format!(
+                    "You survived until wave {} on seed {} and banked {}, defeating {} enemies before falling to {}! Congratulations!",
+                    r.wave_num,
+                    r.seed,
+                    r.banked,
+                    r.kills,
+                    r.cause,
+                ).into());
             }
         }
 
         self.add_records(&owner, sorted_records);
+        self.render_shop(owner);
+    }
+
+    #[export]
+    fn render_shop(&self, owner: Control) {
+        let profile = if let Some(profile) = Profile::get_autoload(unsafe { owner.to_node() }) {
+            profile
+        } else {
+            log::warn!("No profile found, hiding the shop.");
+            return;
+        };
+        profile.map_mut(|profile, _| unsafe {
+            if let Some(mut bank) = owner.get_node(self.bank.new_ref()).and_then(|n| n.cast::<Label>()) {
+                bank.set_text(format!("Bank: {}", profile.bank()).into());
+            }
+            if let Some(mut shop) = owner.get_node(self.shop.new_ref()) {
+                while let Some(child) = shop.get_child(0) {
+                    shop.remove_child(Some(child));
+                }
+                for (id, unlock) in profile.unlocks() {
+                    let (mut buy, entry) = Self::create_unlock_entry(unlock, profile.owns(id), unlock.cost <= profile.bank());
+                    let mut arr = VariantArray::new();
+                    arr.push(&GodotString::from_str(id).to_variant());
+                    buy.connect("button_up".into(), Some(owner.to_object()), "buy_unlock".into(), arr, 0)
+                        .tap_err(|e| log::error!("Could not connect purchase signal due to {:?}.", e));
+                    shop.add_child(Some(entry.to_node()), false);
+                }
+            }
+        }).tap_err(|e| log::error!("Could not render shop due to {:?}.", e));
+    }
+
+    #[export]
+    fn buy_unlock(&self, owner: Control, id: GodotString) {
+        if let Some(profile) = Profile::get_autoload(unsafe { owner.to_node() }) {
+            match profile.map_mut(|profile, _| profile.purchase(&id.to_string())) {
+                Ok(Ok(())) => log::info!("Unlocked {:?}.", id.to_string()),
+                Ok(Err(e)) => log::warn!("Could not unlock {:?} due to {:?}.", id.to_string(), e),
+                Err(e) => log::error!("Could not access profile due to {:?}.", e),
+            }
+        }
+        self.render_shop(owner);
     }
 
     #[export]
@@ -10,13 +10,21 @@ use gdnative::{
 struct Cfg {
     arena_path: NodePath,
     hp_bar_path: NodePath,
+    shield_bar_path: NodePath,
+    overheal_display_path: NodePath,
     wave_display_path: NodePath,
+    seed_display_path: NodePath,
+    status_display_path: NodePath,
 }
 
 impl Cfg {
     const ARENA_PATH: &'static str = "Arena";
     const HP_BAR_PATH: &'static str = "HPBar";
+    const SHIELD_BAR_PATH: &'static str = "ShieldBar";
+    const OVERHEAL_DISPLAY_PATH: &'static str = "Overheal";
     const WAVE_DISPLAY_PATH: &'static str = "WaveNum";
+    const SEED_DISPLAY_PATH: &'static str = "Seed";
+    const STATUS_DISPLAY_PATH: &'static str = "Statuses";
 }
 
 impl Default for Cfg {
@@ -24,7 +32,11 @@ impl Default for Cfg {
         Self {
             arena_path: NodePath::from_str(Self::ARENA_PATH),
             hp_bar_path: NodePath::from_str(Self::HP_BAR_PATH),
+            shield_bar_path: NodePath::from_str(Self::SHIELD_BAR_PATH),
+            overheal_display_path: NodePath::from_str(Self::OVERHEAL_DISPLAY_PATH),
             wave_display_path: NodePath::from_str(Self::WAVE_DISPLAY_PATH),
+            seed_display_path: NodePath::from_str(Self::SEED_DISPLAY_PATH),
+            status_display_path: NodePath::from_str(Self::STATUS_DISPLAY_PATH),
         }
     }
 }
@@ -33,6 +45,9 @@ impl Default for Cfg {
 struct Data {
     max_hp: f64,
     hp: f64,
+    max_shield: f64,
+    shield: f64,
+    overheal: f64,
 }
 
 impl Default for Data {
@@ -40,6 +55,9 @@ impl Default for Data {
         Self {
             max_hp: 100.,
             hp: 100.,
+            max_shield: 0.,
+            shield: 0.,
+            overheal: 0.,
         }
     }
 }
@@ -80,6 +98,22 @@ impl godot::NativeClass for HUD {
             setter: |this: &mut HUD, world| this.cfg.hp_bar_path = world,
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "shield_bar",
+            default: NodePath::from_str(Cfg::SHIELD_BAR_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &HUD| this.cfg.shield_bar_path.new_ref(),
+            setter: |this: &mut HUD, path| this.cfg.shield_bar_path = path,
+            usage: default_usage,
+        });
+        builder.add_property(Property {
+            name: "overheal_display",
+            default: NodePath::from_str(Cfg::OVERHEAL_DISPLAY_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &HUD| this.cfg.overheal_display_path.new_ref(),
+            setter: |this: &mut HUD, path| this.cfg.overheal_display_path = path,
+            usage: default_usage,
+        });
         builder.add_property(Property {
             name: "wave_display",
             default: NodePath::from_str(Cfg::WAVE_DISPLAY_PATH),
@@ -88,6 +122,22 @@ impl godot::NativeClass for HUD {
             setter: |this: &mut HUD, world| this.cfg.wave_display_path = world,
             usage: default_usage,
         });
+        builder.add_property(Property {
+            name: "seed_display",
+            default: NodePath::from_str(Cfg::SEED_DISPLAY_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &HUD| this.cfg.seed_display_path.new_ref(),
+            setter: |this: &mut HUD, path| this.cfg.seed_display_path = path,
+            usage: default_usage,
+        });
+        builder.add_property(Property {
+            name: "status_display",
+            default: NodePath::from_str(Cfg::STATUS_DISPLAY_PATH),
+            hint: PropertyHint::None,
+            getter: |this: &HUD| this.cfg.status_display_path.new_ref(),
+            setter: |this: &mut HUD, path| this.cfg.status_display_path = path,
+            usage: default_usage,
+        });
     }
 }
 
@@ -124,6 +174,46 @@ impl HUD {
         }
     }
 
+    /// The shield bar is hidden while there is no shield to speak of.
+    #[export]
+    fn set_max_shield(&mut self, owner: VBoxContainer, max_shield: u64) {
+        self.data.max_shield = f64::from_bits(max_shield);
+        log::info!("Received max shield data ({}).", self.data.max_shield);
+        if let Some(mut shield_bar) = unsafe {
+            owner.get_node(self.cfg.shield_bar_path.new_ref()).and_then(|n| n.cast::<Range>())
+        } {
+            unsafe {
+                shield_bar.set_max(self.data.max_shield);
+                shield_bar.set_visible(self.data.max_shield > 0.);
+            }
+        }
+    }
+
+    #[export]
+    fn set_shield(&mut self, owner: VBoxContainer, shield: u64) {
+        self.data.shield = f64::from_bits(shield);
+        if let Some(mut shield_bar) = unsafe {
+            owner.get_node(self.cfg.shield_bar_path.new_ref()).and_then(|n| n.cast::<Range>())
+        } {
+            unsafe { shield_bar.set_value(self.data.shield); }
+        }
+    }
+
+    #[export]
+    fn set_overheal(&mut self, owner: VBoxContainer, overheal: u64) {
+        self.data.overheal = f64::from_bits(overheal);
+        if let Some(mut overheal_display) = unsafe {
+            owner.get_node(self.cfg.overheal_display_path.new_ref()).and_then(|n| n.cast::<Label>())
+        } {
+            let text = if self.data.overheal >= 1. {
+                format!("+{:.0}", self.data.overheal)
+            } else {
+                String::new()
+            };
+            unsafe { overheal_display.set_text(text.into()); }
+        }
+    }
+
     #[export]
     fn set_wave_num(&mut self, owner: VBoxContainer, wave_num: u64) {
         if let Some(mut wave_display) = unsafe {
@@ -132,4 +222,27 @@ impl HUD {
             unsafe { wave_display.set_text(format!("{:0>3}", wave_num).into()); }
         }
     }
+
+    #[export]
+    fn set_seed(&mut self, owner: VBoxContainer, seed: u64) {
+        if let Some(mut seed_display) = unsafe {
+            owner.get_node(self.cfg.seed_display_path.new_ref()).and_then(|n| n.cast::<Label>())
+        } {
+            unsafe { seed_display.set_text(format!("{}", seed).into()); }
+        }
+    }
+
+    #[export]
+    fn set_statuses(&mut self, owner: VBoxContainer, statuses: Vec<String>) {
+        if let Some(mut status_display) = unsafe {
+            owner.get_node(self.cfg.status_display_path.new_ref()).and_then(|n| n.cast::<Label>())
+        } {
+            let text = if statuses.is_empty() {
+                "-".to_owned()
+            } else {
+                statuses.join(", ")
+            };
+            unsafe { status_display.set_text(text.into()); }
+        }
+    }
 }
@@ -16,15 +16,17 @@ use gdnative::{
 };
 use tap::TapResultOps;
 
-use crate::systems::items;
+use crate::systems::{equipment::Slot, items};
 
 pub struct Inventory {
     item_grid_path: NodePath,
+    equipment_path: NodePath,
     player: NodePath,
 }
 
 impl Inventory {
     const ITEM_GRID_PATH: &'static str = "";
+    const EQUIPMENT_PATH: &'static str = "";
     const PLAYER_PATH: &'static str = "";
 }
 
@@ -32,6 +34,7 @@ impl Default for Inventory {
     fn default() -> Self {
         Self {
             item_grid_path: Self::ITEM_GRID_PATH.into(),
+            equipment_path: Self::EQUIPMENT_PATH.into(),
             player: Self::PLAYER_PATH.into(),
         }
     }
@@ -58,6 +61,14 @@ impl NativeClass for Inventory {
             setter: |this: &mut Self, path| this.item_grid_path = path,
             usage: PropertyUsage::DEFAULT,
         });
+        builder.add_property(Property {
+            name: "equipment",
+            default: Self::EQUIPMENT_PATH.into(),
+            hint: PropertyHint::None,
+            getter: |this: &Self| this.equipment_path.new_ref(),
+            setter: |this: &mut Self, path| this.equipment_path = path,
+            usage: PropertyUsage::DEFAULT,
+        });
         builder.add_property(Property {
             name: "player",
             default: Self::PLAYER_PATH.into(),
@@ -70,6 +81,16 @@ impl NativeClass for Inventory {
 }
 
 impl Inventory {
+    fn create_empty_box() -> CenterContainer {
+        let mut root = CenterContainer::new();
+        let mut empty_label = Label::new();
+        unsafe {
+            empty_label.set_text("Empty".into());
+            root.add_child(Some(empty_label.to_node()), false);
+        }
+        root
+    }
+
     fn create_item_box(stack: &items::Stack) -> (Button, CenterContainer) {
         let mut root = CenterContainer::new();
         let mut content = VBoxContainer::new();
@@ -77,11 +98,15 @@ impl Inventory {
         let mut desc_label = Label::new();
         let mut quant_label = Label::new();
         let mut use_button = Button::new();
+        let item = items::registry().get(&stack.item);
         unsafe {
-            name_label.set_text(stack.item.name.clone().into());
-            desc_label.set_text(stack.item.desc.clone().into());
+            name_label.set_text(items::registry().name_of(&stack.item).into());
+            desc_label.set_text(item.map_or("", |item| item.desc.as_str()).into());
             quant_label.set_text(stack.count.to_string().into());
-            if stack.item.can_use {
+            if item.map_or(false, |item| item.slot.is_some()) {
+                use_button.set_disabled(false);
+                use_button.set_text("Equip".into());
+            } else if item.map_or(false, |item| item.can_use) {
                 use_button.set_disabled(false);
                 use_button.set_text("Use".into());
             } else {
@@ -96,6 +121,25 @@ impl Inventory {
         }
         (use_button, root)
     }
+
+    fn create_equipped_box(slot: Slot, item: Option<&items::ItemId>) -> (Button, CenterContainer) {
+        let mut root = CenterContainer::new();
+        let mut content = VBoxContainer::new();
+        let mut slot_label = Label::new();
+        let mut name_label = Label::new();
+        let mut unequip_button = Button::new();
+        unsafe {
+            slot_label.set_text(slot.name().into());
+            name_label.set_text(item.map_or("Nothing", |item| items::registry().name_of(item)).into());
+            unequip_button.set_disabled(item.is_none());
+            unequip_button.set_text("Unequip".into());
+            content.add_child(Some(slot_label.to_node()), false);
+            content.add_child(Some(name_label.to_node()), false);
+            content.add_child(Some(unequip_button.to_node()), false);
+            root.add_child(Some(content.to_node()), false);
+        }
+        (unequip_button, root)
+    }
 }
 
 #[methods]
@@ -110,18 +154,41 @@ impl Inventory {
                             while let Some(child) = item_grid.get_child(0) {
                                 item_grid.remove_child(Some(child));
                             }
-                            player.inventory
-                                .stacks()
-                                .map(|stack| (stack, Self::create_item_box(stack)))
-                                .for_each(|(stack, (mut button, item))| {
-                                    let mut arr = VariantArray::new();
-                                    arr.push(&stack.item.to_variant());
-                                    button.connect("button_up".into(), Some(base.to_object()), "use_item".into(), arr, 0)
-                                        .tap_err(|e| log::error!("Could not connect item usage signal due to {:?}.", e));
-                                    button.connect("button_up".into(), Some(owner.to_object()), "render_inventory".into(), VariantArray::new(), 0)
-                                        .tap_err(|e| log::error!("Could not connect item usage signal due to {:?}.", e));
-                                    item_grid.add_child(Some(item.to_node()), false)
-                                });
+                            for slot in player.inventory.slots() {
+                                let stack = if let Some(stack) = slot {
+                                    stack
+                                } else {
+                                    item_grid.add_child(Some(Self::create_empty_box().to_node()), false);
+                                    continue;
+                                };
+                                let (mut button, item) = Self::create_item_box(stack);
+                                let is_equipment = items::registry()
+                                    .get(&stack.item)
+                                    .map_or(false, |item| item.slot.is_some());
+                                let method = if is_equipment { "equip_item" } else { "use_item" };
+                                let mut arr = VariantArray::new();
+                                arr.push(&stack.item.to_variant());
+                                button.connect("button_up".into(), Some(base.to_object()), method.into(), arr, 0)
+                                    .tap_err(|e| log::error!("Could not connect item usage signal due to {:?}.", e));
+                                button.connect("button_up".into(), Some(owner.to_object()), "render_inventory".into(), VariantArray::new(), 0)
+                                    .tap_err(|e| log::error!("Could not connect item usage signal due to {:?}.", e));
+                                item_grid.add_child(Some(item.to_node()), false)
+                            }
+                        }
+                        if let Some(mut equipment) = owner.get_node(self.equipment_path.new_ref()) {
+                            while let Some(child) = equipment.get_child(0) {
+                                equipment.remove_child(Some(child));
+                            }
+                            for &slot in Slot::ALL_SLOTS {
+                                let (mut button, slot_box) = Self::create_equipped_box(slot, player.equipment.get(slot));
+                                let mut arr = VariantArray::new();
+                                arr.push(&GodotString::from_str(slot.name()).to_variant());
+                                button.connect("button_up".into(), Some(base.to_object()), "unequip_item".into(), arr, 0)
+                                    .tap_err(|e| log::error!("Could not connect unequip signal due to {:?}.", e));
+                                button.connect("button_up".into(), Some(owner.to_object()), "render_inventory".into(), VariantArray::new(), 0)
+                                    .tap_err(|e| log::error!("Could not connect unequip signal due to {:?}.", e));
+                                equipment.add_child(Some(slot_box.to_node()), false);
+                            }
                         }
                     })
                     .tap_err(|e| log::error!("Could not render inventory due to {:?}.", e));
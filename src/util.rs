@@ -10,3 +10,5 @@ mod groups;
 pub use groups::Group;
 
 pub mod path_ops;
+
+pub mod rng;
@@ -1,4 +1,5 @@
 use nalgebra as na;
+use crate::util::rng::{self, Stream};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
@@ -112,8 +113,8 @@ impl Direction {
         use rand::seq::SliceRandom;
         use Direction::*;
         const DIRECTIONS: [Direction; 9] = [Up, Right, Down, Left, UpLeft, UpRight, DownLeft, DownRight, Neutral];
-        if let Some(d) = DIRECTIONS.choose(&mut rand::thread_rng()) {
-            *d
+        if let Some(d) = rng::with(Stream::Ai, |rng| DIRECTIONS.choose(rng).copied()) {
+            d
         } else {
             Neutral
         }
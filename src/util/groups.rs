@@ -4,17 +4,20 @@ const ENEMY: &'static str = "enemy";
 const PLAYER: &'static str = "player";
 const SWITCH: &'static str = "switch";
 const PROJECTILE: &'static str = "projectile";
+const PICKUP: &'static str = "pickup";
 
 const PLAYER_MASK: i64 = 1 << 0;
 const PROJECTILE_MASK: i64 = 1 << 2;
 const ENEMY_MASK: i64 = 1 << 1;
 const SWITCH_MASK: i64 = 1 << 3;
+const PICKUP_MASK: i64 = 1 << 4;
 
 lazy_static::lazy_static! {
     static ref ENEMY_GS: GodotString = ENEMY.into();
     static ref PLAYER_GS: GodotString = PLAYER.into();
     static ref PROJECTILE_GS: GodotString = PROJECTILE.into();
     static ref SWITCH_GS: GodotString = SWITCH.into();
+    static ref PICKUP_GS: GodotString = PICKUP.into();
 }
 
 pub enum Group {
@@ -22,10 +25,11 @@ pub enum Group {
     Player,
     Switch,
     Projectile,
+    Pickup,
 }
 
 impl Group {
-    pub const ALL_GROUPS: &'static[Self] = &[Self::Player, Self::Enemy, Self::Projectile, Self::Switch];
+    pub const ALL_GROUPS: &'static[Self] = &[Self::Player, Self::Enemy, Self::Projectile, Self::Switch, Self::Pickup];
 
     pub fn name(&self) -> &'static str {
         match self {
@@ -33,6 +37,7 @@ impl Group {
             Self::Player => PLAYER,
             Self::Projectile => PROJECTILE,
             Self::Switch => &SWITCH,
+            Self::Pickup => PICKUP,
         }
     }
 
@@ -42,6 +47,7 @@ impl Group {
             Self::Player => &PLAYER_GS,
             Self::Projectile => &PROJECTILE_GS,
             Self::Switch => &SWITCH_GS,
+            Self::Pickup => &PICKUP_GS,
         }
     }
 
@@ -51,6 +57,7 @@ impl Group {
             Self::Player => PLAYER_MASK,
             Self::Projectile => PROJECTILE_MASK,
             Self::Switch => SWITCH_MASK,
+            Self::Pickup => PICKUP_MASK,
         }
     }
 
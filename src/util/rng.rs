@@ -0,0 +1,72 @@
+use rand::{rngs::StdRng, SeedableRng};
+use std::sync::{Mutex, PoisonError};
+
+/// Independent random streams, one per subsystem, so that drawing more numbers in one place does not
+/// shift the results everywhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stream {
+    Drops,
+    Spawns,
+    Aim,
+    Ai,
+    Crafting,
+}
+
+impl Stream {
+    pub const ALL_STREAMS: &'static [Self] = &[
+        Self::Drops,
+        Self::Spawns,
+        Self::Aim,
+        Self::Ai,
+        Self::Crafting,
+    ];
+
+    fn idx(&self) -> usize {
+        match self {
+            Self::Drops => 0,
+            Self::Spawns => 1,
+            Self::Aim => 2,
+            Self::Ai => 3,
+            Self::Crafting => 4,
+        }
+    }
+
+    fn seed_for(&self, seed: u64) -> u64 {
+        // Spread the stream index out so neighbouring run seeds do not share streams.
+        seed ^ (self.idx() as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+struct Streams {
+    seed: u64,
+    rngs: Vec<StdRng>,
+}
+
+impl Streams {
+    fn seeded(seed: u64) -> Self {
+        Self {
+            seed,
+            rngs: Stream::ALL_STREAMS.iter().map(|s| StdRng::seed_from_u64(s.seed_for(seed))).collect(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref STREAMS: Mutex<Streams> = Mutex::new(Streams::seeded(rand::random()));
+}
+
+/// Restarts every stream from `seed`.
+pub fn reseed(seed: u64) {
+    log::info!("Seeding rng with {}.", seed);
+    *STREAMS.lock().unwrap_or_else(PoisonError::into_inner) = Streams::seeded(seed);
+}
+
+pub fn seed() -> u64 {
+    STREAMS.lock().unwrap_or_else(PoisonError::into_inner).seed
+}
+
+/// Runs `f` with the rng backing `stream`.
+pub fn with<T>(stream: Stream, f: impl FnOnce(&mut StdRng) -> T) -> T {
+    let mut streams = STREAMS.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut streams.rngs[stream.idx()])
+}